@@ -0,0 +1,36 @@
+use std::time::Instant;
+
+use bare_rs::{BareOptions, Runtime};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+// V8 can only be initialized once per process, so `Runtime::new` can't be
+// measured in a loop: it is timed once, and every benchmark shares the
+// runtime it creates.
+fn setup_teardown(c: &mut Criterion) {
+    let start = Instant::now();
+    let runtime = Runtime::new().expect("Failed to create runtime");
+    println!("Runtime::new: {:?} (single run)", start.elapsed());
+
+    let setup = || runtime.setup(&["bench"], BareOptions::default()).expect("Failed to set up Bare");
+
+    // Dropping the instance tears it down, so keep that out of the timing
+    c.bench_function("setup", |b| b.iter_with_large_drop(setup));
+
+    c.bench_function("teardown", |b| {
+        b.iter_batched(setup, |bare| bare.teardown().expect("Failed to tear down Bare"), BatchSize::PerIteration)
+    });
+
+    c.bench_function("setup_eval_teardown", |b| {
+        b.iter(|| {
+            let bare = setup();
+            bare.eval("1 + 1").expect("Failed to eval");
+            bare.teardown().expect("Failed to tear down Bare")
+        })
+    });
+
+    let bare = setup();
+    c.bench_function("eval", |b| b.iter(|| bare.eval("1 + 1").expect("Failed to eval")));
+}
+
+criterion_group!(benches, setup_teardown);
+criterion_main!(benches);
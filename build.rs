@@ -1,38 +1,80 @@
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn main() {
-    let bare_build_dir = PathBuf::from(env::current_dir().unwrap()).join("bare/build");
-    let bare_build_dir_str = bare_build_dir.to_str().unwrap();
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let target_dir = out_dir.ancestors().find(|p| p.ends_with("target")).unwrap();
+
+    // Link libbare and libuv, and collect the include directories bindgen
+    // needs, bare's own first
+    let include_dirs = if cfg!(feature = "system-bare") {
+        link_system_bare()
+    } else {
+        link_vendored_bare(&out_dir)
+    };
+
+    // Create bindgen builder
+    let bare_header = include_dirs[0].join("bare.h");
+    let mut builder = bindgen::Builder::default()
+        .header(bare_header.to_str().unwrap());
+
+    for include_dir in &include_dirs {
+        let include_arg = format!("-I{}", include_dir.display());
+        println!("Adding include path: {}", include_arg);
+        builder = builder.clang_arg(include_arg);
+    }
+
+    // Generate and write bindings
+    let bindings = builder
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .expect("Unable to generate bindings");
+
+    // Written to OUT_DIR rather than the source tree and pulled in by
+    // `src/bindings.rs`, so builds work from read-only checkouts and leave no
+    // diffs behind. bindgen's CargoCallbacks makes cargo regenerate them only
+    // when one of the headers changes.
+    bindings
+        .write_to_file(out_dir.join("bindings.rs"))
+        .expect("Couldn't write bindings!");
+}
+
+// Build against the bare checkout in `bare/` (or BARE_BUILD_DIR)
+fn link_vendored_bare(out_dir: &Path) -> Vec<PathBuf> {
+    // BARE_BUILD_DIR points at a bare build outside this repo, e.g. when
+    // bare-rs is a dependency of another workspace. The bare sources are
+    // expected in its parent, as with the default `bare/build`.
+    println!("cargo:rerun-if-env-changed=BARE_BUILD_DIR");
+    let bare_build_dir = match env::var_os("BARE_BUILD_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::current_dir().unwrap()).join("bare/build"),
+    };
+    let bare_build_dir_str = bare_build_dir.to_str().unwrap();
+    let bare_include_dir = bare_build_dir.parent()
+        .expect("Bare build directory has no parent")
+        .join("include");
     let profile = out_dir.ancestors().find(|p| p.ends_with("debug") || p.ends_with("release")).unwrap();
 
     // Link directories
     println!("cargo:rustc-link-search={}", bare_build_dir_str);
-    
+
     // Add Homebrew lib path for macOS
     if cfg!(target_os = "macos") {
         // For Apple Silicon Macs
         println!("cargo:rustc-link-search=/opt/homebrew/lib");
         // For Intel Macs
         println!("cargo:rustc-link-search=/usr/local/lib");
-        
+
         // Link libuv
         println!("cargo:rustc-link-lib=uv");
-        
-        // Use dynamic library instead of static
-        println!("cargo:rustc-link-lib=bare");
-        
-        // Copy libbare.dylib to target directory
-        let dylib_src = bare_build_dir.join("libbare.dylib");
-        let dylib_dst = profile.join("libbare.dylib");
-        fs::copy(&dylib_src, &dylib_dst).expect("Failed to copy libbare.dylib");
-        
-        // Add rpath for finding dependencies
-        println!("cargo:rustc-link-arg=-Wl,-rpath,@executable_path");
-        println!("cargo:rustc-link-arg=-Wl,-rpath,@executable_path/.");
+
+        if cfg!(feature = "static-macos") {
+            // Pull all of libbare.a into the binary, as on Linux. ld64 spells
+            // --whole-archive as -force_load, per archive.
+            println!("cargo:rustc-link-arg=-Wl,-force_load,{}/libbare.a", bare_build_dir_str);
+        } else {
+            link_macos_dylib(&bare_build_dir, profile);
+        }
     } else if cfg!(target_os = "windows") {
         println!("cargo:rustc-link-lib=static=bare");
         println!("cargo:rustc-link-lib=uv");
@@ -40,15 +82,25 @@ fn main() {
     } else {
         // Linux
         println!("cargo:rustc-link-lib=uv");
-        println!("cargo:rustc-link-arg=-Wl,--whole-archive");
-        println!("cargo:rustc-link-arg={}/libbare.a", bare_build_dir_str);
-        println!("cargo:rustc-link-arg=-Wl,--no-whole-archive");
+
+        let so_src = bare_build_dir.join("libbare.so");
+        if so_src.exists() {
+            // Shared build of bare: link it dynamically and, as on macOS,
+            // copy it next to the binary so it is found at runtime
+            println!("cargo:rustc-link-lib=bare");
+
+            let so_dst = profile.join("libbare.so");
+            fs::copy(&so_src, &so_dst).expect("Failed to copy libbare.so");
+
+            println!("cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN");
+        } else {
+            println!("cargo:rustc-link-arg=-Wl,--whole-archive");
+            println!("cargo:rustc-link-arg={}/libbare.a", bare_build_dir_str);
+            println!("cargo:rustc-link-arg=-Wl,--no-whole-archive");
+        }
     }
 
-    // Create bindgen builder
-    let mut builder = bindgen::Builder::default()
-        .header("bare/include/bare.h")
-        .clang_arg("-I./bare/include");
+    let mut include_dirs = vec![bare_include_dir];
 
     // Add all deps include directories
     let deps_dir = bare_build_dir.join("_deps");
@@ -59,21 +111,55 @@ fn main() {
             if path.is_dir() {
                 let include_path = path.join("include");
                 if include_path.exists() {
-                    let include_arg = format!("-I{}", include_path.display());
-                    println!("Adding include path: {}", include_arg);
-                    builder = builder.clang_arg(include_arg);
+                    include_dirs.push(include_path);
                 }
             }
         }
     }
 
-    // Generate and write bindings
-    let bindings = builder
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .expect("Unable to generate bindings");
+    include_dirs
+}
 
-    bindings
-        .write_to_file("src/bindings.rs")
-        .expect("Couldn't write bindings!");
-}
\ No newline at end of file
+// Link libbare.dylib and copy it next to the binary, with an rpath so it is
+// found there at runtime
+fn link_macos_dylib(bare_build_dir: &Path, profile: &Path) {
+    // Use dynamic library instead of static
+    println!("cargo:rustc-link-lib=bare");
+
+    // Copy libbare.dylib to target directory
+    let dylib_src = bare_build_dir.join("libbare.dylib");
+    let dylib_dst = profile.join("libbare.dylib");
+    fs::copy(&dylib_src, &dylib_dst).expect("Failed to copy libbare.dylib");
+
+    // Add rpath for finding dependencies
+    println!("cargo:rustc-link-arg=-Wl,-rpath,@executable_path");
+    println!("cargo:rustc-link-arg=-Wl,-rpath,@executable_path/.");
+}
+
+// Build against a system-installed libbare, found through pkg-config. The
+// libraries are linked from wherever the system keeps them, so nothing is
+// copied and no rpath is added.
+#[cfg(feature = "system-bare")]
+fn link_system_bare() -> Vec<PathBuf> {
+    let bare = pkg_config::Config::new()
+        .probe("bare")
+        .expect("Failed to find libbare through pkg-config");
+    let uv = pkg_config::Config::new()
+        .probe("libuv")
+        .expect("Failed to find libuv through pkg-config");
+
+    // Put the directory holding bare.h first, as the header to generate from
+    let (mut include_dirs, others): (Vec<_>, Vec<_>) = bare.include_paths.into_iter()
+        .partition(|dir| dir.join("bare.h").exists());
+    if include_dirs.is_empty() {
+        panic!("pkg-config found libbare but no include directory containing bare.h");
+    }
+    include_dirs.extend(others);
+    include_dirs.extend(uv.include_paths);
+    include_dirs
+}
+
+#[cfg(not(feature = "system-bare"))]
+fn link_system_bare() -> Vec<PathBuf> {
+    unreachable!("system-bare feature is disabled")
+}
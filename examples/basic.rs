@@ -1,7 +1,5 @@
-use bare_rs::{BareResult, init_runtime_once, get_runtime, set_stack_size, handle_js_exception};
+use bare_rs::{BareResult, BareOptions, Runtime, run_with_stack_size, DEFAULT_STACK_SIZE};
 use bare_rs::bindings::*;
-use std::ffi::CString;
-use std::ptr;
 use log::{info, debug, error};
 use env_logger::Env;
 
@@ -41,59 +39,38 @@ fn main() -> BareResult<()> {
         .init();
     
     info!("Starting bare-rs example...");
-    
-    // Set stack size and initialize runtime
-    set_stack_size()?;
-    
-    unsafe {
-        // Initialize the runtime
-        init_runtime_once()?;
-        let runtime = get_runtime()?;
-        
-        // Setup bare runtime options
-        let options = bare_options_t {
-            version: 0,
-            memory_limit: 512 * 1024 * 1024, // 512MB memory limit
-        };
-
-        // Initialize bare runtime
-        let mut bare = ptr::null_mut();
-        let mut env = ptr::null_mut();
-        
-        // Setup arguments
-        let args = vec![
-            CString::new("bare-rs-example").unwrap(),
-            CString::new("--example").unwrap(),
-            CString::new("basic").unwrap(),
-        ];
-        let mut c_args: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
-        
-        debug!("Setting up Bare runtime...");
-        let setup_result = bare_setup(
-            runtime.uv_loop,
-            runtime.platform,
-            &mut env,
-            c_args.len() as i32,
-            c_args.as_mut_ptr(),
-            &options,
-            &mut bare,
-        );
-        
-        if setup_result != 0 {
-            return Err(bare_rs::BareError::SetupError("Failed to setup Bare runtime".into()));
-        }
 
+    // Run on a thread with a larger stack than the main thread's
+    run_with_stack_size(DEFAULT_STACK_SIZE, run_example)
+}
+
+fn run_example() -> BareResult<()> {
+    // Initialize the runtime
+    let runtime = Runtime::new()?;
+
+    // Setup arguments
+    let args = ["bare-rs-example", "--example", "basic"];
+
+    // Setup bare runtime options
+    let options = BareOptions::new()
+        .memory_limit(512 * 1024 * 1024); // 512MB memory limit
+
+    debug!("Setting up Bare runtime...");
+    let bare = runtime.setup(&args, options)?;
+
+    unsafe {
         // Register all available event handlers
         debug!("Registering event handlers...");
-        bare_on_before_exit(bare, Some(before_exit_cb));
-        bare_on_exit(bare, Some(exit_cb));
-        bare_on_teardown(bare, Some(teardown_cb));
-        bare_on_idle(bare, Some(idle_cb));
-        bare_on_suspend(bare, Some(suspend_cb));
-        bare_on_resume(bare, Some(resume_cb));
-        
-        // Example JavaScript code that demonstrates various features
-        let script = CString::new(r#"
+        bare_on_before_exit(bare.as_ptr(), Some(before_exit_cb));
+        bare_on_exit(bare.as_ptr(), Some(exit_cb));
+        bare_on_teardown(bare.as_ptr(), Some(teardown_cb));
+        bare_on_idle(bare.as_ptr(), Some(idle_cb));
+        bare_on_suspend(bare.as_ptr(), Some(suspend_cb));
+        bare_on_resume(bare.as_ptr(), Some(resume_cb));
+    }
+
+    // Example JavaScript code that demonstrates various features
+    let script = r#"
             // Basic console output
             console.log('Hello from bare-rs example!');
             
@@ -146,49 +123,20 @@ fn main() -> BareResult<()> {
                     Bare.exit(0);
                 }
             }, 500);
-        "#).unwrap();
-        
-        let source = uv_buf_t {
-            base: script.as_ptr() as *mut i8,
-            len: script.as_bytes().len(),
-        };
-        
-        let filename = CString::new("example.js").unwrap();
-        let mut result = ptr::null_mut();
-        
-        // Load and run the script
-        debug!("Loading script...");
-        let load_result = bare_load(bare, filename.as_ptr(), &source, &mut result);
-        if load_result != 0 {
-            error!("Failed to load script");
-            let _ = bare_teardown(bare, &mut 1);  // Attempt cleanup
-            return Err(bare_rs::BareError::RuntimeError("Failed to load script".into()));
-        }
-        
-        // Run the script and event loop
-        debug!("Running script and event loop...");
-        let run_result = bare_run(bare);
-        if run_result != 0 {
-            // Check for any JavaScript exceptions
-            if let Err(e) = handle_js_exception(env) {
-                error!("JavaScript error occurred: {}", e);
-                let _ = bare_teardown(bare, &mut 1);  // Attempt cleanup
-                return Err(e);
-            }
-            let _ = bare_teardown(bare, &mut 1);  // Attempt cleanup
-            return Err(bare_rs::BareError::RuntimeError("Failed to run script".into()));
-        }
-        
-        // Cleanup
-        debug!("Cleaning up...");
-        let mut exit_code = 0;
-        let teardown_result = bare_teardown(bare, &mut exit_code);
-        if teardown_result != 0 {
-            error!("Failed to teardown Bare runtime");
-            return Err(bare_rs::BareError::RuntimeError("Failed to teardown Bare runtime".into()));
-        }
-        
-        info!("Example completed successfully with exit code: {}", exit_code);
-        Ok(())
+        "#;
+
+    // Load and run the script and event loop
+    // On the error path below, `bare` is torn down when it is dropped
+    debug!("Running script and event loop...");
+    if let Err(e) = bare.eval_with_filename(script, "example.js") {
+        error!("JavaScript error occurred: {}", e);
+        return Err(e);
     }
-} 
\ No newline at end of file
+
+    // Cleanup
+    debug!("Cleaning up...");
+    let exit_code = bare.teardown()?;
+
+    info!("Example completed successfully with exit code: {}", exit_code);
+    Ok(())
+}
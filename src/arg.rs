@@ -0,0 +1,89 @@
+use std::ptr;
+
+use crate::bindings::*;
+use crate::json;
+use crate::{BareError, BareResult, Value};
+
+/// An argument for `Bare::call_global` or `Value::call_args`, built from a
+/// plain Rust value with `into()`, as in `&[1.5.into(), "px".into()]`, and
+/// turned into a JS value at call time
+#[derive(Debug, Clone)]
+pub enum JsArg<'bare> {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+    /// Converted as `Bare::to_js` does
+    Json(serde_json::Value),
+    /// A value of the instance the call is made on, passed as is
+    Value(Value<'bare>),
+}
+
+impl<'bare> JsArg<'bare> {
+    /// Create the JS value for this argument in `env`
+    pub(crate) fn to_value(&self, env: *mut js_env_t) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        let status = unsafe {
+            match self {
+                JsArg::Number(n) => js_create_double(env, *n, &mut raw),
+                JsArg::Str(s) => js_create_string_utf8(env, s.as_ptr(), s.len(), &mut raw),
+                JsArg::Bool(b) => js_get_boolean(env, *b, &mut raw),
+                JsArg::Null => js_get_null(env, &mut raw),
+                JsArg::Json(json) => return json::to_js(env, json),
+                JsArg::Value(value) => return Ok(*value),
+            }
+        };
+        if status != 0 {
+            return Err(BareError::RuntimeError("Failed to create argument".into()));
+        }
+        Ok(unsafe { Value::from_raw(env, raw) })
+    }
+}
+
+impl From<f64> for JsArg<'_> {
+    fn from(n: f64) -> Self {
+        JsArg::Number(n)
+    }
+}
+
+impl From<i32> for JsArg<'_> {
+    fn from(n: i32) -> Self {
+        JsArg::Number(n as f64)
+    }
+}
+
+impl From<u32> for JsArg<'_> {
+    fn from(n: u32) -> Self {
+        JsArg::Number(n as f64)
+    }
+}
+
+impl From<&str> for JsArg<'_> {
+    fn from(s: &str) -> Self {
+        JsArg::Str(s.to_string())
+    }
+}
+
+impl From<String> for JsArg<'_> {
+    fn from(s: String) -> Self {
+        JsArg::Str(s)
+    }
+}
+
+impl From<bool> for JsArg<'_> {
+    fn from(b: bool) -> Self {
+        JsArg::Bool(b)
+    }
+}
+
+impl From<serde_json::Value> for JsArg<'_> {
+    fn from(json: serde_json::Value) -> Self {
+        JsArg::Json(json)
+    }
+}
+
+impl<'bare> From<Value<'bare>> for JsArg<'bare> {
+    fn from(value: Value<'bare>) -> Self {
+        JsArg::Value(value)
+    }
+}
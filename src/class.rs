@@ -0,0 +1,172 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::bindings::*;
+use crate::function::{self, panic_message, MethodFn};
+use crate::value::type_error;
+use crate::{take_exception, Bare, BareError, BareResult, Value};
+
+/// Closures and wrapped values of the classes defined on a Bare instance.
+///
+/// Each JS instance of a class owns a `Box<dyn Any>` holding its Rust value,
+/// attached with `js_wrap` and dropped by the finalizer once the object is
+/// collected. The addresses of the live ones are tracked so methods only
+/// unwrap objects bare-rs wrapped itself, and so values whose objects are
+/// never collected are still dropped with the instance.
+#[derive(Default)]
+pub(crate) struct Classes {
+    // Boxed so their address is stable while JS holds it as callback data
+    methods: RefCell<Vec<Box<MethodFn>>>,
+    instances: RefCell<HashSet<usize>>,
+}
+
+impl Drop for Classes {
+    // Runs after teardown, when no JS object can reach the values anymore
+    fn drop(&mut self) {
+        for instance in self.instances.get_mut().drain() {
+            drop(unsafe { Box::from_raw(instance as *mut Box<dyn Any>) });
+        }
+    }
+}
+
+/// Builder for a JS class whose instances are backed by Rust values of type
+/// `T`, created through `Bare::define_class`.
+///
+/// `new Name(...args)` calls the constructor closure and attaches the value
+/// it returns to the new object; methods get that value back as `&T`. Use
+/// interior mutability (`Cell`, `RefCell`) for state that methods change.
+pub struct ClassBuilder<'a, 'rt, T> {
+    bare: &'a Bare<'rt>,
+    name: String,
+    constructor: MethodFn,
+    methods: Vec<(String, MethodFn)>,
+    _value: PhantomData<T>,
+}
+
+impl<'a, 'rt, T: 'static> ClassBuilder<'a, 'rt, T> {
+    pub(crate) fn new<F>(bare: &'a Bare<'rt>, name: &str, constructor: F) -> Self
+    where
+        F: Fn(&[Value]) -> BareResult<T> + 'static,
+    {
+        let env = bare.env_ptr();
+        let classes = bare.classes() as *const Classes;
+
+        let constructor: MethodFn = Box::new(move |this, args| {
+            let value: Box<Box<dyn Any>> = Box::new(Box::new(constructor(args)?));
+            let data = Box::into_raw(value);
+            if unsafe { js_wrap(env, this.as_ptr(), data as *mut c_void, Some(finalize), classes as *mut c_void, ptr::null_mut()) } != 0 {
+                drop(unsafe { Box::from_raw(data) });
+                return Err(BareError::RuntimeError("Failed to wrap class instance".into()));
+            }
+            unsafe { &*classes }.instances.borrow_mut().insert(data as usize);
+            Ok(this)
+        });
+
+        ClassBuilder {
+            bare,
+            name: name.to_string(),
+            constructor,
+            methods: Vec::new(),
+            _value: PhantomData,
+        }
+    }
+
+    /// Add a prototype method `name`, which calls `f` with the instance's
+    /// Rust value and the arguments.
+    ///
+    /// Calling the method on anything but an instance of this class, e.g.
+    /// through `Function.prototype.call`, throws a `TypeError`.
+    pub fn method<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: for<'v> Fn(&T, &[Value<'v>]) -> BareResult<Value<'v>> + 'static,
+    {
+        let env = self.bare.env_ptr();
+        let classes = self.bare.classes() as *const Classes;
+        let class = self.name.clone();
+        let method = name.to_string();
+
+        self.methods.push((name.to_string(), Box::new(move |this, args| {
+            let illegal = || type_error(format!("{}.prototype.{} called on an incompatible receiver", class, method));
+
+            let mut data = ptr::null_mut();
+            if unsafe { js_unwrap(env, this.as_ptr(), &mut data) } != 0 {
+                // Objects that wrap nothing fail with an exception of their own
+                let _ = unsafe { take_exception(env) };
+                return Err(illegal());
+            }
+            if !unsafe { &*classes }.instances.borrow().contains(&(data as usize)) {
+                return Err(illegal());
+            }
+            let value = unsafe { &*(data as *const Box<dyn Any>) };
+            f(value.downcast_ref::<T>().ok_or_else(illegal)?, args)
+        })));
+        self
+    }
+
+    /// Define the class, make it the global `name` and return its constructor
+    pub fn register(self) -> BareResult<Value<'a>> {
+        let env = self.bare.env_ptr();
+        let c_name = CString::new(self.name.as_str())?;
+        let classes = self.bare.classes();
+
+        let constructor = Box::new(self.constructor);
+        let mut properties = Vec::with_capacity(self.methods.len());
+        let mut methods = vec![constructor];
+        for (name, method) in self.methods {
+            let method = Box::new(method);
+            properties.push(js_property_descriptor_t {
+                version: 0,
+                name: Value::new_string(self.bare, &name)?.as_ptr(),
+                data: &*method as *const MethodFn as *mut c_void,
+                attributes: (js_writable | js_configurable) as i32,
+                method: Some(function::method_trampoline),
+                getter: None,
+                setter: None,
+                value: ptr::null_mut(),
+            });
+            methods.push(method);
+        }
+
+        let mut class = ptr::null_mut();
+        let status = unsafe {
+            js_define_class(
+                env,
+                c_name.as_ptr(),
+                self.name.len(),
+                Some(function::constructor_trampoline),
+                &*methods[0] as *const MethodFn as *mut c_void,
+                properties.as_ptr(),
+                properties.len(),
+                &mut class,
+            )
+        };
+        if status != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to define class '{}'", self.name)));
+        }
+        classes.methods.borrow_mut().extend(methods);
+
+        let class = unsafe { Value::from_raw(env, class) };
+        self.bare.get_global("globalThis")?.set_property(&self.name, class)?;
+        Ok(class)
+    }
+}
+
+// Drop the Rust value of a collected class instance. `hint` is the
+// `Classes` of the instance that wrapped it.
+unsafe extern "C" fn finalize(_env: *mut js_env_t, data: *mut c_void, hint: *mut c_void) {
+    let classes = &*(hint as *const Classes);
+    if !classes.instances.borrow_mut().remove(&(data as usize)) {
+        return;
+    }
+
+    let value = Box::from_raw(data as *mut Box<dyn Any>);
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| drop(value))) {
+        log::error!("Dropping a class instance panicked: {}", panic_message(&*payload));
+    }
+}
@@ -0,0 +1,51 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+/// Global the capture shim reads its native sink from. It is deleted again
+/// as soon as the shim has run.
+pub(crate) const CAPTURE_FN: &str = "__bareRsCapture";
+
+/// Replaces the console methods with ones that format their arguments and
+/// pass the resulting line to the native sink
+pub(crate) const CAPTURE_SHIM: &str = r#"
+(function (capture) {
+  const format = (arg) => {
+    if (typeof arg === 'string') return arg
+    if (arg instanceof Error) return arg.stack
+    try {
+      const json = JSON.stringify(arg)
+      if (json !== undefined) return json
+    } catch {}
+    return String(arg)
+  }
+
+  for (const method of ['log', 'info', 'debug', 'warn', 'error']) {
+    console[method] = (...args) => {
+      capture(args.map(format).join(' ') + '\n')
+    }
+  }
+})(globalThis.__bareRsCapture)
+
+delete globalThis.__bareRsCapture
+"#;
+
+/// Console output captured through `Bare::capture_output`.
+///
+/// Everything written through `console.log`, `console.error` and friends
+/// accumulates here instead of going to the process stdout/stderr.
+#[derive(Clone, Default)]
+pub struct OutputCapture {
+    buffer: Rc<RefCell<String>>,
+}
+
+impl OutputCapture {
+    /// Drain everything written since capture started or since the last `take`
+    pub fn take(&self) -> String {
+        mem::take(&mut *self.buffer.borrow_mut())
+    }
+
+    pub(crate) fn push(&self, text: &str) {
+        self.buffer.borrow_mut().push_str(text);
+    }
+}
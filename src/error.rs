@@ -1,5 +1,6 @@
 use std::ffi::NulError;
 use std::fmt;
+use std::io;
 
 /// Custom error type for bare-rs
 #[derive(Debug)]
@@ -14,10 +15,25 @@ pub enum BareError {
         message: String,
         stack: Option<String>,
     },
+
+    // Source that failed to compile. The position is only known when the
+    // engine reports it in the stack, so it may be missing.
+    CompileError {
+        message: String,
+        file: String,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
     
     // Resource errors
     MemoryError(String),
     ResourceExhausted(String),
+
+    // Reading scripts or other files failed
+    IoError(io::Error),
+
+    // A string passed to the JS engine contained a NUL byte
+    NulError(NulError),
 }
 
 impl fmt::Display for BareError {
@@ -32,19 +48,135 @@ impl fmt::Display for BareError {
                     write!(f, "{}: {}", error_type, message)
                 }
             },
+            BareError::CompileError { message, file, line, column } => {
+                write!(f, "Compile error in {}", file)?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                    if let Some(column) = column {
+                        write!(f, ":{}", column)?;
+                    }
+                }
+                write!(f, ": {}", message)
+            }
             BareError::MemoryError(msg) => write!(f, "Memory error: {}", msg),
             BareError::ResourceExhausted(msg) => write!(f, "Resource exhausted: {}", msg),
+            BareError::IoError(error) => write!(f, "I/O error: {}", error),
+            // Point at the offending byte so a stray NUL in a script can be found
+            BareError::NulError(error) => write!(
+                f,
+                "String contains a NUL byte at byte offset {}, which cannot be passed to the JS engine",
+                error.nul_position()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BareError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BareError::IoError(error) => Some(error),
+            BareError::NulError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl BareError {
+    /// Classify a `JSError` by its error constructor. A `CompileError` is a
+    /// `SyntaxError`; other variants didn't come from JavaScript and return
+    /// `None`.
+    pub fn js_error_kind(&self) -> Option<JsErrorKind> {
+        match self {
+            BareError::JSError { error_type, .. } => Some(JsErrorKind::from(error_type.as_str())),
+            BareError::CompileError { .. } => Some(JsErrorKind::SyntaxError),
+            _ => None,
+        }
+    }
+
+    /// Parse the stack trace of a `JSError` into frames, innermost first.
+    ///
+    /// Lines that aren't V8 `at ...` frames with a `file:line:column`
+    /// location, such as the leading message or `native` frames, are
+    /// skipped. Errors without a stack have no frames.
+    pub fn stack_frames(&self) -> Vec<StackFrame> {
+        match self {
+            BareError::JSError { stack: Some(stack), .. } => {
+                stack.lines().filter_map(StackFrame::parse).collect()
+            }
+            _ => Vec::new(),
         }
     }
 }
 
-impl std::error::Error for BareError {}
+/// A single frame of a JavaScript stack trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Function name, or `None` for anonymous top-level frames
+    pub function: Option<String>,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl StackFrame {
+    // Parses `at fn (file:line:col)` and `at file:line:col`
+    fn parse(line: &str) -> Option<StackFrame> {
+        let frame = line.trim().strip_prefix("at ")?;
+
+        let (function, location) = match frame.find(" (") {
+            Some(open) if frame.ends_with(')') => {
+                (Some(&frame[..open]), &frame[open + 2..frame.len() - 1])
+            }
+            _ => (None, frame),
+        };
+
+        // The file may itself contain colons, as in `file:///app.js`
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?;
+
+        Some(StackFrame {
+            function: function.map(String::from),
+            file: file.to_string(),
+            line,
+            column,
+        })
+    }
+}
+
+/// Class of a JavaScript error, parsed from its constructor name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsErrorKind {
+    SyntaxError,
+    TypeError,
+    RangeError,
+    ReferenceError,
+    Other(String),
+}
+
+impl From<&str> for JsErrorKind {
+    fn from(error_type: &str) -> Self {
+        match error_type {
+            "SyntaxError" => JsErrorKind::SyntaxError,
+            "TypeError" => JsErrorKind::TypeError,
+            "RangeError" => JsErrorKind::RangeError,
+            "ReferenceError" => JsErrorKind::ReferenceError,
+            other => JsErrorKind::Other(other.to_string()),
+        }
+    }
+}
 
-// Add conversion from NulError to BareError
 impl From<NulError> for BareError {
     fn from(error: NulError) -> Self {
-        BareError::RuntimeError(format!("String contains null byte: {}", error))
+        BareError::NulError(error)
+    }
+}
+
+impl From<io::Error> for BareError {
+    fn from(error: io::Error) -> Self {
+        BareError::IoError(error)
     }
 }
 
-pub type BareResult<T> = Result<T, BareError>; 
\ No newline at end of file
+pub type BareResult<T> = Result<T, BareError>;
@@ -0,0 +1,210 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use std::os::raw::c_void;
+
+use crate::bindings::*;
+use crate::function::panic_message;
+use crate::{error_from_value, BareError, Value};
+
+/// Closures installed through the `Bare::on_*` methods.
+///
+/// Bare's lifecycle callbacks only receive the `bare_t`, so each instance's
+/// handlers are looked up in a thread-local registry keyed by that pointer.
+/// Bare instances are not `Send`, so the callbacks always fire on the thread
+/// that registered them.
+pub(crate) struct EventHandlers {
+    bare: *mut bare_t,
+    env: *mut js_env_t,
+    // Recorded by the exit callback, which is always installed
+    pub(crate) exit_code: Cell<Option<i32>>,
+    pub(crate) before_exit: RefCell<Option<Box<dyn FnMut()>>>,
+    pub(crate) exit: RefCell<Option<Box<dyn FnMut(i32)>>>,
+    pub(crate) idle: RefCell<Option<Box<dyn FnMut()>>>,
+    pub(crate) suspend: RefCell<Option<Box<dyn FnMut(i32)>>>,
+    pub(crate) resume: RefCell<Option<Box<dyn FnMut()>>>,
+    pub(crate) teardown: RefCell<Option<Box<dyn FnMut()>>>,
+    pub(crate) uncaught_exception: RefCell<Option<Box<dyn FnMut(Value) -> bool>>>,
+    pub(crate) unhandled_rejection: RefCell<Option<Box<dyn FnMut(Value)>>>,
+    // Exception the uncaught exception handler declined, or a handler panic,
+    // until it is reported
+    pub(crate) uncaught_error: RefCell<Option<BareError>>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<HashMap<usize, *const EventHandlers>> = RefCell::new(HashMap::new());
+}
+
+impl EventHandlers {
+    pub(crate) fn new(bare: *mut bare_t, env: *mut js_env_t) -> Self {
+        EventHandlers {
+            bare,
+            env,
+            exit_code: Cell::new(None),
+            before_exit: RefCell::new(None),
+            exit: RefCell::new(None),
+            idle: RefCell::new(None),
+            suspend: RefCell::new(None),
+            resume: RefCell::new(None),
+            teardown: RefCell::new(None),
+            uncaught_exception: RefCell::new(None),
+            unhandled_rejection: RefCell::new(None),
+            uncaught_error: RefCell::new(None),
+        }
+    }
+}
+
+/// Make `handlers` reachable from the callbacks of `bare`. The handlers must
+/// stay at the same address until `unregister` is called.
+pub(crate) fn register(bare: *mut bare_t, handlers: &EventHandlers) {
+    REGISTRY.with(|registry| {
+        registry.borrow_mut().insert(bare as usize, handlers as *const EventHandlers);
+    });
+}
+
+pub(crate) fn unregister(bare: *mut bare_t) {
+    REGISTRY.with(|registry| {
+        registry.borrow_mut().remove(&(bare as usize));
+    });
+}
+
+fn with_handlers(bare: *mut bare_t, f: impl FnOnce(&EventHandlers)) {
+    let handlers = REGISTRY.with(|registry| registry.borrow().get(&(bare as usize)).copied());
+    match handlers {
+        Some(handlers) => f(unsafe { &*handlers }),
+        None => log::error!("No event handlers registered for Bare instance"),
+    }
+}
+
+// Unwinding into bare is undefined behavior, so each handler runs under
+// `catch_unwind`. A panic is logged and reported as the instance's error;
+// `terminate` also stops the instance where the loop can still be stopped.
+fn guard(handlers: &EventHandlers, event: &str, terminate: bool, f: impl FnOnce()) {
+    let payload = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(()) => return,
+        Err(payload) => payload,
+    };
+
+    let message = format!("{} handler panicked: {}", event, panic_message(&*payload));
+    log::error!("{}", message);
+    handlers.uncaught_error.borrow_mut().get_or_insert(BareError::RuntimeError(message));
+
+    if terminate && unsafe { bare_terminate(handlers.bare) } != 0 {
+        log::error!("Failed to terminate Bare instance after {} handler panic", event);
+    }
+}
+
+pub(crate) unsafe extern "C" fn on_before_exit(bare: *mut bare_t) {
+    with_handlers(bare, |handlers| {
+        if let Some(callback) = handlers.before_exit.borrow_mut().as_mut() {
+            guard(handlers, "beforeExit", true, callback);
+        }
+    });
+}
+
+pub(crate) unsafe extern "C" fn on_exit(bare: *mut bare_t) {
+    with_handlers(bare, |handlers| {
+        let code = exit_code(handlers.env);
+        handlers.exit_code.set(Some(code));
+        if let Some(callback) = handlers.exit.borrow_mut().as_mut() {
+            guard(handlers, "exit", false, || callback(code));
+        }
+    });
+}
+
+pub(crate) unsafe extern "C" fn on_idle(bare: *mut bare_t) {
+    with_handlers(bare, |handlers| {
+        if let Some(callback) = handlers.idle.borrow_mut().as_mut() {
+            guard(handlers, "idle", true, callback);
+        }
+    });
+}
+
+pub(crate) unsafe extern "C" fn on_suspend(bare: *mut bare_t, linger: i32) {
+    with_handlers(bare, |handlers| {
+        if let Some(callback) = handlers.suspend.borrow_mut().as_mut() {
+            guard(handlers, "suspend", true, || callback(linger));
+        }
+    });
+}
+
+pub(crate) unsafe extern "C" fn on_resume(bare: *mut bare_t) {
+    with_handlers(bare, |handlers| {
+        if let Some(callback) = handlers.resume.borrow_mut().as_mut() {
+            guard(handlers, "resume", true, callback);
+        }
+    });
+}
+
+pub(crate) unsafe extern "C" fn on_teardown(bare: *mut bare_t) {
+    with_handlers(bare, |handlers| {
+        if let Some(callback) = handlers.teardown.borrow_mut().as_mut() {
+            guard(handlers, "teardown", false, callback);
+        }
+    });
+}
+
+// Installed through libjs rather than bare, so the handlers arrive as
+// callback data instead of through the registry
+pub(crate) unsafe extern "C" fn on_uncaught_exception(
+    env: *mut js_env_t,
+    error: *mut js_value_t,
+    data: *mut c_void,
+) {
+    let handlers = &*(data as *const EventHandlers);
+
+    let mut handled = false;
+    if let Some(callback) = handlers.uncaught_exception.borrow_mut().as_mut() {
+        let value = Value::from_raw(env, error);
+        guard(handlers, "uncaughtException", false, || handled = callback(value));
+    }
+    if handled {
+        return;
+    }
+
+    let error = error_from_value(env, error).unwrap_or_else(|e| e);
+    *handlers.uncaught_error.borrow_mut() = Some(error);
+    if bare_terminate(handlers.bare) != 0 {
+        log::error!("Failed to terminate Bare instance after uncaught exception");
+    }
+}
+
+// Installed through libjs like `on_uncaught_exception`. The handler owns the
+// rejection, so the instance keeps running whatever it does.
+pub(crate) unsafe extern "C" fn on_unhandled_rejection(
+    env: *mut js_env_t,
+    reason: *mut js_value_t,
+    _promise: *mut js_value_t,
+    data: *mut c_void,
+) {
+    let handlers = &*(data as *const EventHandlers);
+
+    if let Some(callback) = handlers.unhandled_rejection.borrow_mut().as_mut() {
+        let value = Value::from_raw(env, reason);
+        guard(handlers, "unhandledRejection", false, || callback(value));
+    }
+}
+
+// The exit callback doesn't carry the code, so read `Bare.exitCode` instead
+unsafe fn exit_code(env: *mut js_env_t) -> i32 {
+    let mut global = ptr::null_mut();
+    if js_get_global(env, &mut global) != 0 {
+        log::error!("Failed to get global object");
+        return 0;
+    }
+
+    let exit_code = Value::from_raw(env, global)
+        .get_property("Bare")
+        .and_then(|bare| bare.get_property("exitCode"))
+        .and_then(|code| code.as_f64());
+
+    match exit_code {
+        Ok(code) => code as i32,
+        Err(e) => {
+            log::error!("Failed to read Bare.exitCode: {}", e);
+            0
+        }
+    }
+}
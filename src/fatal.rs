@@ -0,0 +1,64 @@
+use std::sync::Once;
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+// libjs exposes no fatal error callback, so a fatal V8 error (such as running
+// out of heap) ends in abort(). The best we can do is add a line pointing at
+// V8's own message, which it prints just before aborting. Other code in the
+// process may abort too, so the line doesn't claim the engine did.
+#[cfg(unix)]
+const ABORT_MESSAGE: &[u8] =
+    b"bare-rs: process aborted (if V8 reported a fatal error above, that is the cause)\n";
+
+static INSTALL: Once = Once::new();
+
+// The SIGABRT action in place before ours, such as a crash reporter's, which
+// ours hands the signal on to
+#[cfg(unix)]
+static PREVIOUS_ACTION: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// Install a SIGABRT handler that adds a note to fatal JS engine errors
+/// before the process terminates, for `PlatformOptions::abort_diagnostic`.
+/// It only writes that note; nothing can be returned to the caller. The
+/// handler installed before it, if any, still runs afterwards, so the process
+/// aborts as it would have without bare-rs. Installed at most once per
+/// process.
+#[cfg(unix)]
+pub(crate) fn install_abort_handler() {
+    INSTALL.call_once(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_abort as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // Restore the default action once handled, in case the previous one
+        // isn't known yet
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGABRT, &action, &mut previous) != 0 {
+            log::warn!("Failed to install SIGABRT handler");
+            return;
+        }
+        let _ = PREVIOUS_ACTION.set(previous);
+    });
+}
+
+#[cfg(not(unix))]
+pub(crate) fn install_abort_handler() {
+    INSTALL.call_once(|| log::debug!("Fatal error reporting is only available on unix"));
+}
+
+#[cfg(unix)]
+extern "C" fn on_abort(signal: libc::c_int) {
+    // Only async-signal-safe calls are allowed here
+    unsafe {
+        libc::write(libc::STDERR_FILENO, ABORT_MESSAGE.as_ptr() as *const libc::c_void, ABORT_MESSAGE.len());
+
+        // Put the previous action back and raise the signal again. It is
+        // blocked while this handler runs, so it is delivered to that action
+        // as soon as this returns.
+        if let Some(previous) = PREVIOUS_ACTION.get() {
+            libc::sigaction(libc::SIGABRT, previous, std::ptr::null_mut());
+        }
+        libc::raise(signal);
+    }
+}
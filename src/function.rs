@@ -0,0 +1,143 @@
+use std::any::Any;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::bindings::*;
+use crate::value::type_error;
+use crate::{BareError, BareResult, Value};
+
+/// Rust closure backing a native JS function
+pub(crate) type NativeFn = Box<dyn for<'a> Fn(&[Value<'a>]) -> BareResult<Value<'a>>>;
+
+/// Rust closure backing a class constructor or method, called with `this`
+/// and the arguments
+pub(crate) type MethodFn = Box<dyn for<'a> Fn(Value<'a>, &[Value<'a>]) -> BareResult<Value<'a>>>;
+
+/// Create a JS function that calls `callback`.
+///
+/// The closure is not owned by the JS function: the caller must keep it alive,
+/// at a stable address, for as long as the function can be called.
+pub(crate) unsafe fn create_function(
+    env: *mut js_env_t,
+    name: &str,
+    callback: &NativeFn,
+) -> BareResult<*mut js_value_t> {
+    let c_name = CString::new(name)?;
+    let mut function = ptr::null_mut();
+
+    if js_create_function(
+        env,
+        c_name.as_ptr(),
+        name.len(),
+        Some(trampoline),
+        callback as *const NativeFn as *mut c_void,
+        &mut function,
+    ) != 0 {
+        return Err(BareError::RuntimeError(format!("Failed to create function '{}'", name)));
+    }
+
+    Ok(function)
+}
+
+// Bridges a JS call to the closure passed as callback data
+unsafe extern "C" fn trampoline(env: *mut js_env_t, info: *mut js_callback_info_t) -> *mut js_value_t {
+    bridge(env, info, |data, _receiver, args| (*(data as *const NativeFn))(args))
+}
+
+/// Like `trampoline`, for a `MethodFn` passed as callback data, which is
+/// also given `this`
+pub(crate) unsafe extern "C" fn method_trampoline(
+    env: *mut js_env_t,
+    info: *mut js_callback_info_t,
+) -> *mut js_value_t {
+    bridge(env, info, |data, receiver, args| (*(data as *const MethodFn))(receiver, args))
+}
+
+/// Like `method_trampoline`, for class constructors: `this` is the new
+/// object, and calls without `new` are rejected
+pub(crate) unsafe extern "C" fn constructor_trampoline(
+    env: *mut js_env_t,
+    info: *mut js_callback_info_t,
+) -> *mut js_value_t {
+    let mut new_target = ptr::null_mut();
+    if js_get_new_target(env, info, &mut new_target) != 0 || new_target.is_null()
+        || Value::from_raw(env, new_target).is_undefined().unwrap_or(true)
+    {
+        throw_error(env, &type_error("Class constructor cannot be invoked without 'new'".into()));
+        return ptr::null_mut();
+    }
+    method_trampoline(env, info)
+}
+
+// Collect the arguments, receiver and callback data of a JS call and pass
+// them to `call`, throwing its error, if any, as a JS error
+unsafe fn bridge(
+    env: *mut js_env_t,
+    info: *mut js_callback_info_t,
+    call: impl for<'a> FnOnce(*mut c_void, Value<'a>, &[Value<'a>]) -> BareResult<Value<'a>>,
+) -> *mut js_value_t {
+    let mut argc = 0;
+    let mut receiver = ptr::null_mut();
+    let mut data = ptr::null_mut();
+    if js_get_callback_info(env, info, &mut argc, ptr::null_mut(), &mut receiver, &mut data) != 0 {
+        throw_error(env, &BareError::RuntimeError("Failed to get callback info".into()));
+        return ptr::null_mut();
+    }
+
+    let mut argv = vec![ptr::null_mut(); argc];
+    if argc > 0 && js_get_callback_info(env, info, &mut argc, argv.as_mut_ptr(), ptr::null_mut(), ptr::null_mut()) != 0 {
+        throw_error(env, &BareError::RuntimeError("Failed to get callback arguments".into()));
+        return ptr::null_mut();
+    }
+
+    let args: Vec<Value> = argv.iter().map(|raw| Value::from_raw(env, *raw)).collect();
+    let receiver = Value::from_raw(env, receiver);
+
+    // Unwinding into the JS engine is undefined behavior, so panics are
+    // caught here and rethrown as JS errors
+    match panic::catch_unwind(AssertUnwindSafe(|| call(data, receiver, &args))) {
+        Ok(Ok(value)) => value.as_ptr(),
+        Ok(Err(e)) => {
+            throw_error(env, &e);
+            ptr::null_mut()
+        }
+        Err(payload) => {
+            let message = format!("Native function panicked: {}", panic_message(&*payload));
+            throw_error(env, &BareError::RuntimeError(message));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Throw `error` as a pending JS exception, keeping the JS error class
+/// for `BareError::JSError`
+pub(crate) unsafe fn throw_error(env: *mut js_env_t, error: &BareError) {
+    let (error_type, message) = match error {
+        BareError::JSError { error_type, message, .. } => (error_type.as_str(), message.clone()),
+        e => ("Error", e.to_string()),
+    };
+    let message = CString::new(message.replace('\0', ""))
+        .unwrap_or_default();
+
+    let status = match error_type {
+        "TypeError" => js_throw_type_error(env, ptr::null(), message.as_ptr()),
+        "RangeError" => js_throw_range_error(env, ptr::null(), message.as_ptr()),
+        "SyntaxError" => js_throw_syntax_error(env, ptr::null(), message.as_ptr()),
+        _ => js_throw_error(env, ptr::null(), message.as_ptr()),
+    };
+    if status != 0 {
+        log::error!("Failed to throw JS error: {}", error);
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".into()
+    }
+}
@@ -0,0 +1,124 @@
+use std::ptr;
+
+use crate::bindings::*;
+use crate::{BareError, BareResult, Value, ValueType};
+
+/// Format a value for humans, roughly like `util.inspect` with a depth of
+/// one: the value itself is expanded, and any objects or arrays inside it are
+/// summarized as `[Object]` or `[Array]`.
+pub(crate) fn to_debug_string(value: &Value) -> BareResult<String> {
+    // Errors are most useful with their stack, which includes the message
+    if value.type_of()? == ValueType::Object && value.instance_of("Error")? {
+        let stack = value.get_property("stack")?;
+        if stack.type_of()? == ValueType::String {
+            return stack.as_string();
+        }
+    }
+    format_value(value, true)
+}
+
+fn format_value(value: &Value, expand: bool) -> BareResult<String> {
+    match value.type_of()? {
+        ValueType::Undefined => Ok("undefined".into()),
+        ValueType::Null => Ok("null".into()),
+        ValueType::String => Ok(quote(&value.as_string()?)),
+        ValueType::Boolean | ValueType::Number | ValueType::Symbol => js_string(value),
+        ValueType::BigInt => Ok(format!("{}n", js_string(value)?)),
+        ValueType::External => Ok("[External]".into()),
+        ValueType::Function => {
+            let name = value.get_property("name")?;
+            match name.type_of()? {
+                ValueType::String if !name.as_string()?.is_empty() => {
+                    Ok(format!("[Function: {}]", name.as_string()?))
+                }
+                _ => Ok("[Function (anonymous)]".into()),
+            }
+        }
+        ValueType::Object => {
+            let is_array = unsafe {
+                let mut is_array = false;
+                if js_is_array(value.env_ptr(), value.as_ptr(), &mut is_array) != 0 {
+                    return Err(BareError::RuntimeError("Failed to check for array".into()));
+                }
+                is_array
+            };
+
+            match (is_array, expand) {
+                (true, true) => format_array(value),
+                (true, false) => Ok("[Array]".into()),
+                (false, true) => format_object(value),
+                (false, false) => Ok("[Object]".into()),
+            }
+        }
+    }
+}
+
+fn format_array(value: &Value) -> BareResult<String> {
+    let items = (0..value.length()?)
+        .map(|i| format_value(&value.get_index(i)?, false))
+        .collect::<BareResult<Vec<_>>>()?;
+
+    if items.is_empty() {
+        return Ok("[]".into());
+    }
+    Ok(format!("[ {} ]", items.join(", ")))
+}
+
+fn format_object(value: &Value) -> BareResult<String> {
+    let env = value.env_ptr();
+    let names = unsafe {
+        let mut names = ptr::null_mut();
+        if js_get_property_names(env, value.as_ptr(), &mut names) != 0 {
+            return Err(BareError::RuntimeError("Failed to get property names".into()));
+        }
+        Value::from_raw(env, names)
+    };
+
+    let mut fields = Vec::new();
+    for i in 0..names.length()? {
+        let name = names.get_index(i)?;
+        let field = unsafe {
+            let mut field = ptr::null_mut();
+            if js_get_property(env, value.as_ptr(), name.as_ptr(), &mut field) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to get property {}", i)));
+            }
+            Value::from_raw(env, field)
+        };
+
+        let name = js_string(&name)?;
+        fields.push(format!("{}: {}", format_key(&name), format_value(&field, false)?));
+    }
+
+    if fields.is_empty() {
+        return Ok("{}".into());
+    }
+    Ok(format!("{{ {} }}", fields.join(", ")))
+}
+
+// `String(value)`, which unlike coercion also handles symbols
+fn js_string(value: &Value) -> BareResult<String> {
+    let global = unsafe {
+        let mut global = ptr::null_mut();
+        if js_get_global(value.env_ptr(), &mut global) != 0 {
+            return Err(BareError::RuntimeError("Failed to get global object".into()));
+        }
+        Value::from_raw(value.env_ptr(), global)
+    };
+    global.get_property("String")?.call(&[*value])?.as_string()
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n"))
+}
+
+// Keys that are valid identifiers are shown bare, like in JS source
+fn format_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
@@ -0,0 +1,682 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use base64::Engine;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::bindings::*;
+use crate::function::panic_message;
+use crate::{BareError, BareResult, Value};
+
+// Requests made by bare-rs itself use ids from here up, so their responses
+// can be told apart from the client's and aren't relayed to it
+const INTERNAL_ID_BASE: u64 = 1 << 40;
+
+// Larger WebSocket messages are refused rather than buffered
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Connections served at once, each on a thread of its own. Further ones are
+// closed right away.
+const MAX_CONNECTIONS: usize = 8;
+
+// Limit on the request line and headers of an HTTP request, and the time a
+// client gets to send them
+const MAX_HEAD_SIZE: u64 = 16 * 1024;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Inspector server for a single JS environment, for Chrome DevTools and
+/// other Chrome DevTools Protocol clients to attach to.
+///
+/// libjs only provides an in-process inspector session, so this serves the
+/// protocol's HTTP discovery endpoints and a WebSocket for one client at a
+/// time itself, relaying messages between the socket and the session.
+/// Messages arrive on network threads and are dispatched on the instance's
+/// loop, or right away while the debugger holds the script paused.
+///
+/// Anyone who can reach the server can run code in the instance, so requests
+/// are only served if their `Host` header is `localhost` or an IP address.
+/// This keeps web pages from reaching it through DNS rebinding.
+pub(crate) struct Inspector {
+    // Boxed so the address handed to the callbacks stays stable
+    session: Box<Session>,
+    wakeup: *mut uv_async_t,
+    addr: SocketAddr,
+    id: String,
+}
+
+// State used by the callbacks on the JS thread
+struct Session {
+    env: *mut js_env_t,
+    inspector: *mut js_inspector_t,
+    shared: Arc<Shared>,
+    events: Receiver<Event>,
+    // Set once the client asks the script to start running
+    run_requested: Cell<bool>,
+    next_id: Cell<u64>,
+}
+
+// State shared between the JS thread and the network threads
+struct Shared {
+    // Write half of the attached client's socket
+    client: Mutex<Option<TcpStream>>,
+    // Cleared before the handle is closed, so it is never woken afterwards
+    wakeup: Mutex<Option<Wakeup>>,
+    closed: AtomicBool,
+    // Every open connection, shut down when the inspector is dropped so the
+    // threads serving them stop
+    connections: Mutex<HashMap<u64, TcpStream>>,
+    next_connection: AtomicU64,
+}
+
+// Removes a connection from `Shared::connections` once it has been served
+struct Connection {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+struct Wakeup(*mut uv_async_t);
+
+// uv_async_send is the one libuv function that may be called from any thread
+unsafe impl Send for Wakeup {}
+
+enum Event {
+    Message(String),
+    Disconnected,
+}
+
+impl Inspector {
+    /// Listen for clients on `addr` and connect them to the inspector of
+    /// `env`, dispatching their messages on `uv_loop`
+    pub(crate) unsafe fn start(env: *mut js_env_t, uv_loop: *mut uv_loop_t, addr: SocketAddr) -> BareResult<Inspector> {
+        let listener = TcpListener::bind(addr)?;
+        // Port 0 picks a free port, so report the one actually bound
+        let addr = listener.local_addr()?;
+
+        let mut inspector = ptr::null_mut();
+        if js_create_inspector(env, &mut inspector) != 0 {
+            return Err(BareError::RuntimeError("Failed to create inspector".into()));
+        }
+
+        let shared = Arc::new(Shared {
+            client: Mutex::new(None),
+            wakeup: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            connections: Mutex::new(HashMap::new()),
+            next_connection: AtomicU64::new(0),
+        });
+        let (sender, events) = mpsc::channel();
+
+        // From here on, Drop cleans up
+        let mut this = Inspector {
+            session: Box::new(Session {
+                env,
+                inspector,
+                shared: shared.clone(),
+                events,
+                run_requested: Cell::new(false),
+                next_id: Cell::new(INTERNAL_ID_BASE),
+            }),
+            wakeup: ptr::null_mut(),
+            addr,
+            id: session_id(),
+        };
+
+        let data = &*this.session as *const Session as *mut c_void;
+        if js_on_inspector_response(env, inspector, Some(on_response), data) != 0
+            || js_on_inspector_paused(env, inspector, Some(on_paused), data) != 0
+            || js_connect_inspector(env, inspector) != 0
+        {
+            return Err(BareError::RuntimeError("Failed to connect inspector".into()));
+        }
+
+        let wakeup = Box::into_raw(Box::new(std::mem::zeroed::<uv_async_t>()));
+        if uv_async_init(uv_loop, wakeup, Some(on_wakeup)) != 0 {
+            drop(Box::from_raw(wakeup));
+            return Err(BareError::RuntimeError("Failed to initialize inspector wakeup".into()));
+        }
+        (*wakeup).data = data;
+        // Waiting for clients shouldn't keep the loop alive
+        uv_unref(wakeup as *mut uv_handle_t);
+        this.wakeup = wakeup;
+        *lock(&shared.wakeup) = Some(Wakeup(wakeup));
+
+        let server = Arc::new(Server {
+            listener,
+            shared,
+            sender: Mutex::new(sender),
+            addr,
+            id: this.id.clone(),
+        });
+        thread::Builder::new()
+            .name("bare-rs-inspector".into())
+            .spawn(move || server.serve())?;
+
+        Ok(this)
+    }
+
+    /// WebSocket URL for clients to attach to
+    pub(crate) fn url(&self) -> String {
+        format!("ws://{}/{}", self.addr, self.id)
+    }
+
+    /// Block until a client has attached and asked the script to run, then
+    /// set a breakpoint on the first line of `filename`
+    pub(crate) fn wait_for_debugger(&self, filename: &str) -> BareResult<()> {
+        // The loop isn't running yet, so dispatch messages from here
+        while !self.session.run_requested.get() {
+            match self.session.events.recv() {
+                Ok(event) => self.session.handle(event),
+                Err(_) => return Err(BareError::RuntimeError("Inspector server stopped".into())),
+            }
+        }
+
+        // Scripts are known to V8 by URL, which ends in the file name
+        let name = Path::new(filename).file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| filename.to_string());
+        let url_regex = format!("(^|[/\\\\]){}$", regex_escape(&name));
+
+        self.session.send("Debugger.enable", json!({}))?;
+        self.session.send("Debugger.setBreakpointByUrl", json!({ "lineNumber": 0, "urlRegex": url_regex }))
+    }
+}
+
+impl Drop for Inspector {
+    fn drop(&mut self) {
+        let shared = &self.session.shared;
+        shared.closed.store(true, Ordering::SeqCst);
+
+        unsafe {
+            if !self.wakeup.is_null() {
+                *lock(&shared.wakeup) = None;
+                uv_close(self.wakeup as *mut uv_handle_t, Some(on_close));
+            }
+            if js_destroy_inspector(self.session.env, self.session.inspector) != 0 {
+                log::error!("Failed to destroy inspector");
+            }
+        }
+
+        if let Some(client) = lock(&shared.client).take() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+        // Connection threads blocked on a read return with an error
+        for connection in lock(&shared.connections).values() {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+        // Wake the server thread from accept so it sees it is closed. An
+        // unspecified address isn't connectable everywhere, but the listener
+        // also accepts on the loopback address of its family.
+        let mut wake = self.addr;
+        match wake.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => wake.set_ip(Ipv4Addr::LOCALHOST.into()),
+            IpAddr::V6(ip) if ip.is_unspecified() => wake.set_ip(Ipv6Addr::LOCALHOST.into()),
+            _ => {}
+        }
+        let _ = TcpStream::connect(wake);
+    }
+}
+
+impl Session {
+    fn handle(&self, event: Event) {
+        let result = match event {
+            Event::Message(message) => self.dispatch(&message),
+            // Resume the script and drop the client's breakpoints, so a
+            // detached debugger doesn't leave it stuck
+            Event::Disconnected => self.send("Debugger.disable", json!({})),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to dispatch inspector message: {}", e);
+        }
+    }
+
+    // Send a request of our own; its response is dropped by on_response
+    fn send(&self, method: &str, params: serde_json::Value) -> BareResult<()> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.dispatch(&json!({ "id": id, "method": method, "params": params }).to_string())
+    }
+
+    fn dispatch(&self, message: &str) -> BareResult<()> {
+        let method = serde_json::from_str::<serde_json::Value>(message).ok()
+            .and_then(|message| message.get("method")?.as_str().map(String::from));
+        if method.as_deref() == Some("Runtime.runIfWaitingForDebugger") {
+            self.run_requested.set(true);
+        }
+
+        // Messages are also dispatched from the loop, outside of any scope
+        unsafe {
+            let mut scope = ptr::null_mut();
+            if js_open_handle_scope(self.env, &mut scope) != 0 {
+                return Err(BareError::RuntimeError("Failed to open handle scope".into()));
+            }
+
+            let mut request = ptr::null_mut();
+            let status = if js_create_string_utf8(self.env, message.as_ptr(), message.len(), &mut request) != 0 {
+                -1
+            } else {
+                js_send_inspector_request(self.env, self.inspector, request)
+            };
+
+            js_close_handle_scope(self.env, scope);
+            if status != 0 {
+                return Err(BareError::RuntimeError("Failed to send inspector request".into()));
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn on_response(
+    env: *mut js_env_t,
+    _inspector: *mut js_inspector_t,
+    message: *mut js_value_t,
+    data: *mut c_void,
+) {
+    let session = &*(data as *const Session);
+    // Unwinding into the inspector is undefined behavior
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let message = match Value::from_raw(env, message).as_string() {
+            Ok(message) => message,
+            Err(e) => {
+                log::error!("Failed to read inspector response: {}", e);
+                return;
+            }
+        };
+
+        let id = serde_json::from_str::<serde_json::Value>(&message).ok()
+            .and_then(|message| message.get("id")?.as_u64());
+        match id {
+            Some(id) if id >= INTERNAL_ID_BASE => {
+                if message.contains("\"error\"") {
+                    log::error!("Inspector request failed: {}", message);
+                }
+            }
+            _ => session.shared.send_to_client(0x1, message.as_bytes()),
+        }
+    }));
+    if let Err(payload) = result {
+        log::error!("Inspector response handler panicked: {}", panic_message(&*payload));
+    }
+}
+
+// libjs calls this in a loop while the debugger holds the script paused,
+// until the script is resumed or this returns false. Each call handles one
+// message, as the client is what resumes the script.
+unsafe extern "C" fn on_paused(_env: *mut js_env_t, _inspector: *mut js_inspector_t, data: *mut c_void) -> bool {
+    let session = &*(data as *const Session);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match session.events.recv() {
+        Ok(event) => {
+            session.handle(event);
+            true
+        }
+        Err(_) => false,
+    }));
+    result.unwrap_or_else(|payload| {
+        log::error!("Inspector pause handler panicked: {}", panic_message(&*payload));
+        false
+    })
+}
+
+unsafe extern "C" fn on_wakeup(handle: *mut uv_async_t) {
+    let session = &*((*handle).data as *const Session);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        while let Ok(event) = session.events.try_recv() {
+            session.handle(event);
+        }
+    }));
+    if let Err(payload) = result {
+        log::error!("Inspector message handler panicked: {}", panic_message(&*payload));
+    }
+}
+
+unsafe extern "C" fn on_close(handle: *mut uv_handle_t) {
+    drop(Box::from_raw(handle as *mut uv_async_t));
+}
+
+impl Shared {
+    // Write a WebSocket frame to the attached client, if there is one,
+    // dropping the client if that fails
+    fn send_to_client(&self, opcode: u8, payload: &[u8]) {
+        let mut client = lock(&self.client);
+        if let Some(stream) = client.as_mut() {
+            if let Err(e) = write_frame(stream, opcode, payload) {
+                log::debug!("Inspector client went away: {}", e);
+                let _ = stream.shutdown(Shutdown::Both);
+                *client = None;
+            }
+        }
+    }
+
+    // Track `stream` until the returned guard is dropped, or refuse it if
+    // the inspector is closed or serving as many connections as it may
+    fn register(self: &Arc<Self>, stream: &TcpStream) -> Option<Connection> {
+        let mut connections = lock(&self.connections);
+        if self.closed.load(Ordering::SeqCst) || connections.len() >= MAX_CONNECTIONS {
+            return None;
+        }
+        let id = self.next_connection.fetch_add(1, Ordering::SeqCst);
+        connections.insert(id, stream.try_clone().ok()?);
+        Some(Connection { shared: self.clone(), id })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        lock(&self.shared.connections).remove(&self.id);
+    }
+}
+
+// Accepts connections on its own thread, with a thread per connection up to
+// MAX_CONNECTIONS
+struct Server {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    sender: Mutex<Sender<Event>>,
+    addr: SocketAddr,
+    id: String,
+}
+
+impl Server {
+    fn serve(self: Arc<Self>) {
+        for stream in self.listener.incoming() {
+            if self.shared.closed.load(Ordering::SeqCst) {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let Some(connection) = self.shared.register(&stream) else {
+                        log::debug!("Refusing inspector connection: too many connections");
+                        let _ = stream.shutdown(Shutdown::Both);
+                        continue;
+                    };
+                    let server = self.clone();
+                    let spawned = thread::Builder::new()
+                        .name("bare-rs-inspector-connection".into())
+                        .spawn(move || {
+                            let _connection = connection;
+                            if let Err(e) = server.handle(stream) {
+                                log::debug!("Inspector connection failed: {}", e);
+                            }
+                        });
+                    if let Err(e) = spawned {
+                        log::error!("Failed to start inspector connection thread: {}", e);
+                    }
+                }
+                Err(e) => log::error!("Failed to accept inspector connection: {}", e),
+            }
+        }
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        stream.set_read_timeout(Some(HEAD_TIMEOUT))?;
+        let (path, host, key) = read_head(&mut reader)?;
+        stream.set_read_timeout(None)?;
+
+        if !host.as_deref().is_some_and(is_allowed_host) {
+            log::debug!("Refusing inspector request for host {:?}", host);
+            return respond(&mut stream, "403 Forbidden", "");
+        }
+
+        match (path.as_str(), key) {
+            (path, Some(key)) if path == format!("/{}", self.id) => self.attach(reader, stream, &key),
+            ("/json" | "/json/list", None) => respond(&mut stream, "200 OK", &self.targets().to_string()),
+            ("/json/version", None) => {
+                let version = json!({
+                    "Browser": concat!("bare-rs/", env!("CARGO_PKG_VERSION")),
+                    "Protocol-Version": "1.3",
+                });
+                respond(&mut stream, "200 OK", &version.to_string())
+            }
+            _ => respond(&mut stream, "404 Not Found", ""),
+        }
+    }
+
+    // Targets listed for discovery, in the format DevTools expects
+    fn targets(&self) -> serde_json::Value {
+        let address = format!("{}/{}", self.addr, self.id);
+        json!([{
+            "description": "bare-rs instance",
+            "devtoolsFrontendUrl": format!(
+                "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}", address
+            ),
+            "id": self.id,
+            "title": "bare-rs",
+            "type": "node",
+            "url": "file://",
+            "webSocketDebuggerUrl": format!("ws://{}", address),
+        }])
+    }
+
+    // Complete the WebSocket handshake and relay the client's messages until
+    // it goes away
+    fn attach(&self, mut reader: BufReader<TcpStream>, mut stream: TcpStream, key: &str) -> io::Result<()> {
+        {
+            let mut client = lock(&self.shared.client);
+            if client.is_some() {
+                return respond(&mut stream, "409 Conflict", "");
+            }
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )?;
+            *client = Some(stream);
+        }
+
+        let result = self.relay(&mut reader);
+        if let Some(client) = lock(&self.shared.client).take() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+        self.send(Event::Disconnected);
+        result
+    }
+
+    fn relay(&self, reader: &mut BufReader<TcpStream>) -> io::Result<()> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = read_frame(reader, MAX_MESSAGE_SIZE - message.len() as u64)?;
+            match opcode {
+                // Text, possibly continued over several frames
+                0x0 | 0x1 => {
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        let text = String::from_utf8(std::mem::take(&mut message))
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                        self.send(Event::Message(text));
+                    }
+                }
+                0x8 => {
+                    self.shared.send_to_client(0x8, &payload);
+                    return Ok(());
+                }
+                0x9 => self.shared.send_to_client(0xA, &payload),
+                _ => {}
+            }
+        }
+    }
+
+    // Queue an event for the JS thread and wake its loop
+    fn send(&self, event: Event) {
+        if lock(&self.sender).send(event).is_err() {
+            return;
+        }
+        if let Some(wakeup) = lock(&self.shared.wakeup).as_ref() {
+            unsafe {
+                uv_async_send(wakeup.0);
+            }
+        }
+    }
+}
+
+// A panicking holder can't leave these in an inconsistent state
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Read the request line and headers of an HTTP request, returning the path
+// and the Host and Sec-WebSocket-Key headers
+fn read_head(reader: &mut BufReader<TcpStream>) -> io::Result<(String, Option<String>, Option<String>)> {
+    let mut head = reader.take(MAX_HEAD_SIZE);
+
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+
+    let (mut host, mut key) = (None, None);
+    loop {
+        let mut line = String::new();
+        if head.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Incomplete or oversized request"));
+        }
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    Ok((path, host, key))
+}
+
+// Whether `host`, the value of a Host header, names this machine in a way a
+// DNS rebinding attack can't redirect: `localhost` or an IP address, with an
+// optional port
+fn is_allowed_host(host: &str) -> bool {
+    let (name, rest) = match host.strip_prefix('[') {
+        // IPv6 addresses are bracketed, as in `[::1]:9229`
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some(split) => split,
+            None => return false,
+        },
+        None => host.find(':').map_or((host, ""), |i| host.split_at(i)),
+    };
+    let port_valid = match rest.strip_prefix(':') {
+        Some(port) => !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()),
+        None => rest.is_empty(),
+    };
+    port_valid && (name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json; charset=UTF-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Returns whether this is the final frame, the opcode and the unmasked
+// payload, which may be at most `limit` bytes long
+fn read_frame(reader: &mut impl Read, limit: u64) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too large"));
+    }
+
+    // Frames from clients are always masked
+    let mut mask = [0u8; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+
+    // Grow the buffer as data arrives rather than trusting the length
+    let mut payload = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut payload)?;
+    if (payload.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+// Random id in the UUID format DevTools uses for targets
+fn session_id() -> String {
+    let a = RandomState::new().build_hasher().finish();
+    let b = RandomState::new().build_hasher().finish();
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        a >> 32,
+        (a >> 16) & 0xffff,
+        a & 0xffff,
+        b >> 48,
+        b & 0xffff_ffff_ffff
+    )
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The Sec-WebSocket-Accept value answering a handshake with `key`
+fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key, WEBSOCKET_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
@@ -0,0 +1,135 @@
+use std::ptr;
+
+use serde_json::{Map, Number};
+
+use crate::bindings::*;
+use crate::value::{range_error, type_error};
+use crate::{BareError, BareResult, Value, ValueType};
+
+// Deeper structures are almost certainly cyclic
+const MAX_DEPTH: usize = 128;
+
+/// Convert a JSON value into a JS value in `env`.
+///
+/// Numbers become JS doubles, so integers beyond 2^53 lose precision just as
+/// they would through `JSON.parse`.
+pub(crate) unsafe fn to_js<'bare>(env: *mut js_env_t, json: &serde_json::Value) -> BareResult<Value<'bare>> {
+    let mut raw = ptr::null_mut();
+
+    let status = match json {
+        serde_json::Value::Null => js_get_null(env, &mut raw),
+        serde_json::Value::Bool(b) => js_get_boolean(env, *b, &mut raw),
+        serde_json::Value::Number(n) => {
+            let n = n.as_f64()
+                .ok_or_else(|| range_error(format!("Number {} does not fit in a double", n)))?;
+            js_create_double(env, n, &mut raw)
+        }
+        serde_json::Value::String(s) => js_create_string_utf8(env, s.as_ptr(), s.len(), &mut raw),
+        serde_json::Value::Array(items) => {
+            if js_create_array_with_length(env, items.len(), &mut raw) != 0 {
+                return Err(BareError::RuntimeError("Failed to create array".into()));
+            }
+            for (i, item) in items.iter().enumerate() {
+                let item = to_js(env, item)?;
+                if js_set_element(env, raw, i as u32, item.as_ptr()) != 0 {
+                    return Err(BareError::RuntimeError(format!("Failed to set array element {}", i)));
+                }
+            }
+            0
+        }
+        serde_json::Value::Object(fields) => {
+            if js_create_object(env, &mut raw) != 0 {
+                return Err(BareError::RuntimeError("Failed to create object".into()));
+            }
+            for (key, field) in fields {
+                let field = to_js(env, field)?;
+                // A JS string key, as JSON keys may contain NULs
+                let mut js_key = ptr::null_mut();
+                if js_create_string_utf8(env, key.as_ptr(), key.len(), &mut js_key) != 0
+                    || js_set_property(env, raw, js_key, field.as_ptr()) != 0
+                {
+                    return Err(BareError::RuntimeError(format!("Failed to set property '{}'", key)));
+                }
+            }
+            0
+        }
+    };
+
+    if status != 0 {
+        return Err(BareError::RuntimeError("Failed to create JS value from JSON".into()));
+    }
+
+    Ok(Value::from_raw(env, raw))
+}
+
+/// Convert a JS value into JSON.
+///
+/// Objects contribute their own enumerable string-keyed properties, like
+/// with `JSON.stringify`; inherited ones are left out.
+///
+/// `undefined` maps to `null`, as do the non-finite numbers `NaN` and
+/// `Infinity`, which JSON cannot represent. Functions, symbols, externals and
+/// bigints are rejected with a `TypeError`.
+pub(crate) fn to_json(value: &Value) -> BareResult<serde_json::Value> {
+    to_json_at_depth(value, 0)
+}
+
+fn to_json_at_depth(value: &Value, depth: usize) -> BareResult<serde_json::Value> {
+    if depth > MAX_DEPTH {
+        return Err(range_error("Value is nested too deeply to convert to JSON".into()));
+    }
+
+    match value.type_of()? {
+        ValueType::Undefined | ValueType::Null => Ok(serde_json::Value::Null),
+        ValueType::Boolean => Ok(serde_json::Value::Bool(value.as_bool()?)),
+        ValueType::Number => Ok(number_to_json(value.as_f64()?)),
+        ValueType::String => Ok(serde_json::Value::String(value.as_string()?)),
+        ValueType::Object => unsafe { object_to_json(value, depth) },
+        other => Err(type_error(format!("Cannot convert {} to JSON", other))),
+    }
+}
+
+fn number_to_json(n: f64) -> serde_json::Value {
+    // Keep integral values as JSON integers so `42` doesn't become `42.0`
+    if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+        return serde_json::Value::Number(Number::from(n as i64));
+    }
+    Number::from_f64(n)
+        .map(serde_json::Value::Number)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+unsafe fn object_to_json(value: &Value, depth: usize) -> BareResult<serde_json::Value> {
+    let env = value.env_ptr();
+    let raw = value.as_ptr();
+
+    let mut is_array = false;
+    if js_is_array(env, raw, &mut is_array) != 0 {
+        return Err(BareError::RuntimeError("Failed to check for array".into()));
+    }
+
+    if is_array {
+        let mut len = 0;
+        if js_get_array_length(env, raw, &mut len) != 0 {
+            return Err(BareError::RuntimeError("Failed to get array length".into()));
+        }
+
+        let mut items = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            let mut item = ptr::null_mut();
+            if js_get_element(env, raw, i, &mut item) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to get array element {}", i)));
+            }
+            items.push(to_json_at_depth(&Value::from_raw(env, item), depth + 1)?);
+        }
+        return Ok(serde_json::Value::Array(items));
+    }
+
+    // Only own enumerable properties, as with JSON.stringify
+    let mut fields = Map::new();
+    for name in value.keys()? {
+        let field = value.get_property(&name)?;
+        fields.insert(name, to_json_at_depth(&field, depth + 1)?);
+    }
+    Ok(serde_json::Value::Object(fields))
+}
@@ -1,81 +1,1628 @@
+mod arg;
 pub mod bindings;
+mod class;
+mod console;
+mod error;
+mod events;
+mod fatal;
+mod function;
+mod inspect;
+#[cfg(feature = "inspector")]
+mod inspector;
+mod json;
+mod metrics;
+mod modules;
+mod profiler;
+mod sandbox;
+mod timer;
+mod value;
+mod worker;
 
-use std::ffi::NulError;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
 use std::fmt;
+use std::marker::PhantomData;
+#[cfg(feature = "inspector")]
+use std::net::SocketAddr;
+use std::path::Path;
 use std::ptr;
+use std::rc::Rc;
 use libc;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Per-step logging around FFI calls, such as the exception checks below. It
+// runs on hot paths, so it is compiled in only with the `trace-ffi` feature.
+// Exported for the CLI, which traces its startup the same way; the feature is
+// checked here rather than in the expansion, so it is this crate's that counts.
+#[cfg(feature = "trace-ffi")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! trace_ffi {
+    ($($arg:tt)*) => {
+        ::log::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "trace-ffi"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! trace_ffi {
+    ($($arg:tt)*) => {};
+}
 
 use bindings::*;
+use class::Classes;
+use events::EventHandlers;
+use function::NativeFn;
+#[cfg(feature = "inspector")]
+use inspector::Inspector;
+use profiler::Profiler;
+use sandbox::Sandbox;
+use timer::Timers;
+
+pub use arg::JsArg;
+pub use class::ClassBuilder;
+pub use console::OutputCapture;
+pub use error::{BareError, BareResult, JsErrorKind, StackFrame};
+pub use metrics::RuntimeMetrics;
+pub use sandbox::{SANDBOX_GLOBALS, SANDBOX_REMOVED_BARE_MEMBERS};
+pub use value::{Value, ValueType};
+pub use worker::Worker;
 
 // Global runtime storage using lazy_static
 lazy_static::lazy_static! {
     static ref RUNTIME: Mutex<Option<GlobalRuntime>> = Mutex::new(None);
 }
 
-/// Custom error type for bare-rs
-#[derive(Debug)]
-pub enum BareError {
-    // System level errors
-    RuntimeError(String),
-    SetupError(String),
-    
-    // JavaScript errors
-    JSError {
-        error_type: String,
-        message: String,
-        stack: Option<String>,
-    },
-    
-    // Resource errors
-    MemoryError(String),
-    ResourceExhausted(String),
-}
-
-impl fmt::Display for BareError {
+pub struct GlobalRuntime {
+    pub uv_loop: *mut uv_loop_t,
+    pub platform: *mut js_platform_t,
+}
+
+// Mark GlobalRuntime as thread safe since we control access through Mutex
+unsafe impl Send for GlobalRuntime {}
+unsafe impl Sync for GlobalRuntime {}
+
+// Set once a JS platform has been created. V8 can only be initialized once
+// per process, and not again after it has been torn down.
+static PLATFORM_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Owned handle to the UV loop and JS platform that Bare instances run on.
+///
+/// This is the preferred alternative to `init_runtime_once`/`get_runtime`:
+/// instead of a process-wide singleton, the loop and platform belong to this
+/// value and are released when it is dropped.
+///
+/// V8 supports a single platform per process, so only one `Runtime` can ever
+/// be created; later attempts fail with `BareError::RuntimeError`. Run several
+/// independent scripts by setting up multiple `Bare` instances on it instead.
+///
+/// With the `loop-per-instance` feature, `Runtime::shared` hands out a
+/// `SharedRuntime` that other threads can set up instances through, each on a
+/// loop of its own. The runtime itself stays bound to one thread, since
+/// instances from `setup` share its loop.
+pub struct Runtime {
+    uv_loop: *mut uv_loop_t,
+    platform: *mut js_platform_t,
+    // Profile every instance set up on this runtime at this interval
+    sampling_profiler_interval: Option<i32>,
+    expose_gc: bool,
+    // Serve an inspector for every instance set up on this runtime here
+    #[cfg(feature = "inspector")]
+    inspector_addr: Option<SocketAddr>,
+}
+
+impl Runtime {
+    /// Create a new UV loop and JS platform with the default platform options
+    pub fn new() -> BareResult<Runtime> {
+        Runtime::with_platform_options(PlatformOptions::default())
+    }
+
+    /// Create a new UV loop and a JS platform configured by `options`
+    pub fn with_platform_options(options: PlatformOptions) -> BareResult<Runtime> {
+        if PLATFORM_CREATED.swap(true, Ordering::SeqCst) {
+            return Err(BareError::RuntimeError(
+                "A JS platform has already been created in this process; only one Runtime is supported".into()
+            ));
+        }
+
+        let runtime = unsafe { Runtime::create(options) };
+        if runtime.is_err() {
+            // Nothing was initialized, so a later attempt may still succeed
+            PLATFORM_CREATED.store(false, Ordering::SeqCst);
+        }
+        runtime
+    }
+
+    unsafe fn create(options: PlatformOptions) -> BareResult<Runtime> {
+        let uv_loop = uv_loop_new();
+        if uv_loop.is_null() {
+            // uv_loop_new has no status code, but leaves errno set by the failed
+            // allocation or loop initialization
+            return Err(BareError::RuntimeError(format!(
+                "Failed to create UV loop: uv_loop_new returned null ({})",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        // Say where the SIGABRT came from if V8 hits a fatal error
+        if options.abort_diagnostic {
+            fatal::install_abort_handler();
+        }
+
+        let mut platform = ptr::null_mut();
+        let mut platform_options = options.build();
+
+        let status = js_create_platform(uv_loop, &mut platform_options, &mut platform);
+        if status != 0 {
+            uv_loop_delete(uv_loop);
+            return Err(BareError::RuntimeError(format!(
+                "Failed to create JS platform: js_create_platform returned {}", status
+            )));
+        }
+
+        Ok(Runtime {
+            uv_loop,
+            platform,
+            sampling_profiler_interval: options.sampling_profiler_interval,
+            expose_gc: options.expose_gc,
+            #[cfg(feature = "inspector")]
+            inspector_addr: options.inspector_addr,
+        })
+    }
+
+    /// Name and version of the JS engine behind this runtime, such as
+    /// `"v8 12.4.254.21"`
+    pub fn version(&self) -> BareResult<String> {
+        unsafe {
+            let mut identifier = ptr::null();
+            let mut version = ptr::null();
+            if js_get_platform_identifier(self.platform, &mut identifier) != 0
+                || js_get_platform_version(self.platform, &mut version) != 0
+            {
+                return Err(BareError::RuntimeError("Failed to get JS platform version".into()));
+            }
+            Ok(format!(
+                "{} {}",
+                CStr::from_ptr(identifier).to_string_lossy(),
+                CStr::from_ptr(version).to_string_lossy()
+            ))
+        }
+    }
+
+    /// Set up a Bare instance on this runtime.
+    ///
+    /// `args` becomes the argv of the instance, so the first entry is
+    /// conventionally the program name. The instance is torn down when the
+    /// returned `Bare` is dropped, or explicitly through `Bare::teardown`.
+    pub fn setup(&self, args: &[&str], options: BareOptions) -> BareResult<Bare<'_>> {
+        unsafe { self.setup_on(self.uv_loop, false, args, options) }
+    }
+
+    /// Handle for setting up instances on loops of their own from other
+    /// threads, see `SharedRuntime`
+    #[cfg(feature = "loop-per-instance")]
+    pub fn shared(&self) -> SharedRuntime<'_> {
+        SharedRuntime { runtime: self }
+    }
+
+    // Set up a Bare instance on `uv_loop`, which the instance deletes after
+    // its teardown if `owns_loop` is set
+    unsafe fn setup_on(
+        &self,
+        uv_loop: *mut uv_loop_t,
+        owns_loop: bool,
+        args: &[&str],
+        options: BareOptions,
+    ) -> BareResult<Bare<'_>> {
+        let args = args.iter()
+            .map(|arg| CString::new(*arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut c_args: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
+        let sandboxed = options.sandbox;
+        let options = options.build();
+
+        let mut bare = ptr::null_mut();
+        let mut env = ptr::null_mut();
+
+        let setup_result = bare_setup(
+            uv_loop,
+            self.platform,
+            &mut env,
+            c_args.len() as i32,
+            c_args.as_mut_ptr(),
+            &options,
+            &mut bare,
+        );
+
+        if setup_result != 0 {
+            if owns_loop {
+                uv_loop_delete(uv_loop);
+            }
+            return Err(BareError::SetupError("Failed to setup Bare runtime".into()));
+        }
+
+        let events = Box::new(EventHandlers::new(bare, env));
+        events::register(bare, &events);
+
+        let mut bare = Bare {
+            raw: bare,
+            env,
+            uv_loop,
+            owns_loop,
+            torn_down: false,
+            events,
+            functions: RefCell::new(Vec::new()),
+            classes: Box::default(),
+            profiler: None,
+            #[cfg(feature = "inspector")]
+            inspector: None,
+            sandbox: None,
+            timers: Timers::new(),
+            gc_exposed: self.expose_gc,
+            memory_limit: options.memory_limit,
+            metrics: Rc::default(),
+            _runtime: PhantomData,
+        };
+        metrics::register(env, &bare.metrics);
+
+        // Always listen for exit so the code is available through `exit_code`
+        if bare_on_exit(bare.raw, Some(events::on_exit)) != 0 {
+            return Err(BareError::SetupError("Failed to register exit handler".into()));
+        }
+
+        if let Some(interval_us) = self.sampling_profiler_interval {
+            bare.profiler = Some(Profiler::start(env, interval_us)?);
+        }
+
+        if sandboxed {
+            bare.sandbox = Some(sandbox::apply(&bare)?);
+        }
+
+        #[cfg(feature = "inspector")]
+        if let Some(addr) = self.inspector_addr {
+            bare.inspector = Some(Inspector::start(env, uv_loop, addr)?);
+        }
+
+        Ok(bare)
+    }
+
+    /// Raw pointer to the UV loop owned by this runtime
+    pub fn uv_loop(&self) -> *mut uv_loop_t {
+        self.uv_loop
+    }
+
+    /// Raw pointer to the JS platform owned by this runtime
+    pub fn platform(&self) -> *mut js_platform_t {
+        self.platform
+    }
+}
+
+// Raw pointers say nothing useful in debug output, so only report whether
+// they are set
+impl fmt::Debug for Runtime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            BareError::RuntimeError(msg) => write!(f, "Runtime error: {}", msg),
-            BareError::SetupError(msg) => write!(f, "Setup error: {}", msg),
-            BareError::JSError { error_type, message, stack } => {
-                if let Some(stack_trace) = stack {
-                    write!(f, "{}: {}\nStack trace:\n{}", error_type, message, stack_trace)
-                } else {
-                    write!(f, "{}: {}", error_type, message)
-                }
+        f.debug_struct("Runtime")
+            .field("has_loop", &!self.uv_loop.is_null())
+            .field("has_platform", &!self.platform.is_null())
+            .finish()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        unsafe {
+            // The platform is created on top of the loop (see `create`), so
+            // it has to be destroyed first
+            if js_destroy_platform(self.platform) != 0 {
+                log::error!("Failed to destroy JS platform");
+            }
+            uv_loop_delete(self.uv_loop);
+        }
+    }
+}
+
+/// Thread-safe handle to a `Runtime`, from `Runtime::shared`, that can only
+/// set up instances on UV loops of their own.
+///
+/// Instances on separate loops share nothing but the JS platform, so they can
+/// run concurrently on different threads, such as one per request of a
+/// server.
+#[cfg(feature = "loop-per-instance")]
+#[derive(Clone, Copy)]
+pub struct SharedRuntime<'rt> {
+    runtime: &'rt Runtime,
+}
+
+// The platform is thread-safe: bare itself creates environments on it from
+// the threads behind `Bare.Thread`. The runtime's loop is not, which is why
+// this handle never touches it.
+#[cfg(feature = "loop-per-instance")]
+unsafe impl Send for SharedRuntime<'_> {}
+#[cfg(feature = "loop-per-instance")]
+unsafe impl Sync for SharedRuntime<'_> {}
+
+#[cfg(feature = "loop-per-instance")]
+impl<'rt> SharedRuntime<'rt> {
+    /// Set up a Bare instance like `Runtime::setup`, but on a UV loop of its
+    /// own, deleted after the instance is torn down.
+    ///
+    /// Each instance is bound to the thread that set it up: `Bare` isn't
+    /// `Send`, and its values, timers and event loop must only be used from
+    /// there.
+    pub fn setup_on_new_loop(&self, args: &[&str], options: BareOptions) -> BareResult<Bare<'rt>> {
+        unsafe {
+            let uv_loop = uv_loop_new();
+            if uv_loop.is_null() {
+                return Err(BareError::SetupError(format!(
+                    "Failed to create UV loop: uv_loop_new returned null ({})",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            self.runtime.setup_on(uv_loop, true, args, options)
+        }
+    }
+}
+
+/// Default JS heap limit for a Bare instance (1GB)
+pub const DEFAULT_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
+
+/// Options for setting up a Bare instance, built with
+/// `BareOptions::new().memory_limit(bytes).version(v)`
+#[derive(Debug, Clone, Copy)]
+pub struct BareOptions {
+    version: i32,
+    memory_limit: usize,
+    sandbox: bool,
+}
+
+impl BareOptions {
+    /// Options with the defaults: version 0 and a `DEFAULT_MEMORY_LIMIT` heap
+    pub fn new() -> Self {
+        BareOptions {
+            version: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            sandbox: false,
+        }
+    }
+
+    /// Limit the JS heap of each thread to `bytes`
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Restrict what scripts can reach, for running untrusted code: after
+    /// setup, every global not in `SANDBOX_GLOBALS` is deleted, and so are
+    /// the members of `Bare` in `SANDBOX_REMOVED_BARE_MEMBERS`.
+    ///
+    /// This takes away the escape hatches bare itself provides, such as
+    /// exiting the process and loading native addons. It is not a security
+    /// boundary on its own: `require` can still load JS packages reachable
+    /// from the script's location, so run untrusted scripts from a directory
+    /// without any, and bound their resources with `memory_limit` and
+    /// `Bare::run_with_timeout`.
+    pub fn sandbox(mut self) -> Self {
+        self.sandbox = true;
+        self
+    }
+
+    /// Version of the `bare_options_t` struct to pass to Bare
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Produce the FFI options struct passed to `bare_setup`
+    pub fn build(&self) -> bare_options_t {
+        bare_options_t {
+            version: self.version,
+            memory_limit: self.memory_limit,
+        }
+    }
+}
+
+impl Default for BareOptions {
+    fn default() -> Self {
+        BareOptions::new()
+    }
+}
+
+/// Heap usage of a Bare instance, as reported by `Bare::heap_stats`. All
+/// sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Memory currently committed for the JS heap
+    pub total_heap_size: usize,
+    /// Size of all objects living in the JS heap
+    pub used_heap_size: usize,
+    /// Memory held outside the heap, by array buffers and external strings
+    pub external_memory: usize,
+    /// The `memory_limit` the instance was set up with, or `None` if V8
+    /// infers it from the physical memory of the device
+    pub heap_size_limit: Option<usize>,
+}
+
+/// A Bare instance and its JS environment, set up through `Runtime::setup`.
+///
+/// The instance is torn down exactly once: either explicitly through
+/// `teardown`, or implicitly when the handle is dropped.
+pub struct Bare<'rt> {
+    raw: *mut bare_t,
+    env: *mut js_env_t,
+    uv_loop: *mut uv_loop_t,
+    // Whether `uv_loop` was created for this instance alone
+    owns_loop: bool,
+    torn_down: bool,
+    events: Box<EventHandlers>,
+    // Closures backing native functions, boxed so their address is stable
+    functions: RefCell<Vec<Box<NativeFn>>>,
+    // Dropped after teardown, which may still finalize class instances
+    classes: Box<Classes>,
+    profiler: Option<Profiler>,
+    #[cfg(feature = "inspector")]
+    inspector: Option<Inspector>,
+    sandbox: Option<Sandbox>,
+    timers: Timers,
+    // Whether the runtime lets `collect_garbage` request collections
+    gc_exposed: bool,
+    // Heap limit from the options given to setup, with 0 for inferred
+    memory_limit: usize,
+    // Shared with the registry through which exceptions are counted
+    metrics: Rc<RefCell<RuntimeMetrics>>,
+    _runtime: PhantomData<&'rt Runtime>,
+}
+
+impl<'rt> Bare<'rt> {
+    /// Version of the linked bare library, such as `"1.17.3"`
+    pub fn version() -> BareResult<String> {
+        let (mut major, mut minor, mut patch) = (0, 0, 0);
+        if unsafe { bare_version(&mut major, &mut minor, &mut patch) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get bare version".into()));
+        }
+        Ok(format!("{}.{}.{}", major, minor, patch))
+    }
+
+    /// Raw pointer to the underlying `bare_t`
+    pub fn as_ptr(&self) -> *mut bare_t {
+        self.raw
+    }
+
+    /// Raw pointer to the JS environment of this instance
+    pub fn env_ptr(&self) -> *mut js_env_t {
+        self.env
+    }
+
+    /// Return the pending JS exception, if any, as a `BareError::JSError`,
+    /// clearing it from the environment. An uncaught exception that the
+    /// handler set through `set_uncaught_exception_handler` declined is
+    /// returned here too.
+    pub fn check_exception(&self) -> BareResult<()> {
+        let uncaught = self.events.uncaught_error.borrow_mut().take();
+        match uncaught {
+            Some(error) => Err(error),
+            None => unsafe { try_handle_js_exception(self.env) },
+        }
+    }
+
+    /// Register a Rust closure as a global JS function named `name`.
+    ///
+    /// Errors returned by the closure are thrown as JS errors. Panics are
+    /// caught and thrown as JS errors too, rather than unwinding into the
+    /// JS engine.
+    pub fn set_global_fn<F>(&self, name: &str, f: F) -> BareResult<()>
+    where
+        F: for<'a> Fn(&[Value<'a>]) -> BareResult<Value<'a>> + 'static,
+    {
+        let callback: Box<NativeFn> = Box::new(Box::new(f));
+        let c_name = CString::new(name)?;
+
+        unsafe {
+            let function = function::create_function(self.env, name, &callback)?;
+
+            if js_set_named_property(self.env, self.global()?, c_name.as_ptr(), function) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to set global '{}'", name)));
+            }
+        }
+
+        self.functions.borrow_mut().push(callback);
+        Ok(())
+    }
+
+    /// Start defining a JS class `name` whose instances are backed by Rust
+    /// values of type `T`, such as a database handle with a `query()` method.
+    ///
+    /// `constructor` builds the value for `new Name(...args)`. Add methods
+    /// to the returned builder, then `register` it as a global. Each value
+    /// is dropped when the garbage collector finalizes its JS object, or at
+    /// the latest when the instance is torn down.
+    pub fn define_class<T, F>(&self, name: &str, constructor: F) -> ClassBuilder<'_, 'rt, T>
+    where
+        T: 'static,
+        F: Fn(&[Value]) -> BareResult<T> + 'static,
+    {
+        ClassBuilder::new(self, name, constructor)
+    }
+
+    pub(crate) fn classes(&self) -> &Classes {
+        &self.classes
+    }
+
+    /// Read the global variable `name`, or `undefined` if it isn't set
+    pub fn get_global(&self, name: &str) -> BareResult<Value<'_>> {
+        let c_name = CString::new(name)?;
+
+        unsafe {
+            let mut value = ptr::null_mut();
+            if js_get_named_property(self.env, self.global()?, c_name.as_ptr(), &mut value) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to get global '{}'", name)));
+            }
+            Ok(Value::from_raw(self.env, value))
+        }
+    }
+
+    /// Set the global variable `name` to `value`, e.g. to inject
+    /// configuration converted with `to_js` before a script runs
+    pub fn set_global(&self, name: &str, value: Value) -> BareResult<()> {
+        if value.env_ptr() != self.env {
+            return Err(BareError::RuntimeError(format!(
+                "Cannot set global '{}' to a value from another environment", name
+            )));
+        }
+        let c_name = CString::new(name)?;
+
+        unsafe {
+            if js_set_named_property(self.env, self.global()?, c_name.as_ptr(), value.as_ptr()) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to set global '{}'", name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Make the global `name` read-only: the binding can no longer be
+    /// reassigned or deleted, and its value is frozen with `Object.freeze`.
+    ///
+    /// The freeze is shallow, so objects nested inside the value stay
+    /// mutable; use `freeze_global_deep` for those too.
+    pub fn freeze_global(&self, name: &str) -> BareResult<()> {
+        self.lock_global(name, false)
+    }
+
+    /// Like `freeze_global`, but also freeze every object reachable through
+    /// the value's enumerable properties
+    pub fn freeze_global_deep(&self, name: &str) -> BareResult<()> {
+        self.lock_global(name, true)
+    }
+
+    fn lock_global(&self, name: &str, deep: bool) -> BareResult<()> {
+        let value = self.get_global(name)?;
+        if value.is_undefined()? {
+            return Err(value::type_error(format!("Global '{}' is not defined", name)));
+        }
+
+        let object = self.get_global("Object")?;
+        let descriptor = self.to_js(&serde_json::json!({ "writable": false, "configurable": false }))?;
+        let global = unsafe { Value::from_raw(self.env, self.global()?) };
+        object.get_property("defineProperty")?
+            .call_with_this(object, &[global, Value::new_string(self, name)?, descriptor])?;
+
+        self.freeze(object, value, deep)
+    }
+
+    fn freeze(&self, object: Value, value: Value, deep: bool) -> BareResult<()> {
+        if !matches!(value.type_of()?, ValueType::Object | ValueType::Function) {
+            return Ok(());
+        }
+        // Already frozen objects are skipped, which also ends cycles
+        if deep && object.get_property("isFrozen")?.call_with_this(object, &[value])?.as_bool()? {
+            return Ok(());
+        }
+
+        object.get_property("freeze")?.call_with_this(object, &[value])?;
+
+        if deep {
+            let values = object.get_property("values")?.call_with_this(object, &[value])?;
+            for i in 0..values.length()? {
+                self.freeze(object, values.get_index(i)?, deep)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The JS global object of this instance
+    unsafe fn global(&self) -> BareResult<*mut js_value_t> {
+        let mut global = ptr::null_mut();
+        if js_get_global(self.env, &mut global) != 0 {
+            return Err(BareError::RuntimeError("Failed to get global object".into()));
+        }
+        Ok(global)
+    }
+
+    /// Load and run `code`, returning the result of loading it.
+    ///
+    /// For CommonJS sources the result is the module's `module.exports`.
+    pub fn eval(&self, code: &str) -> BareResult<Value<'_>> {
+        self.eval_with_filename(code, "[eval]")
+    }
+
+    /// Like `eval`, with `filename` used for module resolution and stack traces.
+    ///
+    /// A relative `filename` is resolved against the current working
+    /// directory, and `require`/`import` specifiers in `code` resolve
+    /// relative to it. The resolved path is the script's `__filename` and
+    /// `import.meta.url`, and its directory `__dirname`, so code without a
+    /// file of its own can be given a logical location, e.g.
+    /// `/srv/app/[eval]` to resolve requires from `/srv/app`. The file
+    /// itself doesn't need to exist.
+    pub fn eval_with_filename(&self, code: &str, filename: &str) -> BareResult<Value<'_>> {
+        let result = self.load(code, filename)?;
+        self.run()?;
+        Ok(result)
+    }
+
+    /// Run `source` as a classic script in a fresh JS context, with a global
+    /// object of its own, and return its completion value.
+    ///
+    /// The context shares the instance's isolate, heap limits and microtask
+    /// queue, but its globals are only the ECMAScript built-ins: there is no
+    /// `Bare`, `console`, `require` or timers, and nothing it defines is seen
+    /// by other scripts. The context is destroyed once the script returns.
+    /// Objects passed between contexts, such as the result, still reach the
+    /// context that created them, so this isolates globals rather than being
+    /// a security boundary.
+    pub fn eval_isolated(&self, source: &str) -> BareResult<Value<'_>> {
+        let filename = "[isolated]";
+        let c_filename = CString::new(filename)?;
+        let script = Value::new_string(self, source)?;
+
+        unsafe {
+            let mut context = ptr::null_mut();
+            if js_create_context(self.env, &mut context) != 0 {
+                return Err(BareError::RuntimeError("Failed to create context".into()));
+            }
+            if js_enter_context(self.env, context) != 0 {
+                js_destroy_context(self.env, context);
+                return Err(BareError::RuntimeError("Failed to enter context".into()));
+            }
+
+            let mut result = ptr::null_mut();
+            let status = js_run_script(
+                self.env, c_filename.as_ptr(), filename.len(), 0, script.as_ptr(), &mut result,
+            );
+
+            js_exit_context(self.env, context);
+            js_destroy_context(self.env, context);
+
+            if status != 0 {
+                self.check_exception()?;
+                return Err(BareError::RuntimeError(format!("Failed to run {}", filename)));
+            }
+            Ok(Value::from_raw(self.env, result))
+        }
+    }
+
+    /// Load the module at `path` through bare's module loader and run it,
+    /// returning its exports.
+    ///
+    /// The file is read by bare itself, so its format (CommonJS, ESM, JSON,
+    /// ...) is picked from the extension as it would be by the `bare` CLI.
+    /// A relative `path` is resolved against the current working directory
+    /// up front; `require`/`import` specifiers inside the module then resolve
+    /// relative to the module's own location, wherever the process runs from.
+    /// A missing `path` is reported as `BareError::IoError`, and a module
+    /// that doesn't compile as `BareError::CompileError`, as with `load`.
+    pub fn load_module<P: AsRef<Path>>(&self, path: P) -> BareResult<Value<'_>> {
+        let path = path.as_ref().canonicalize()?;
+        let filename = path.to_str()
+            .ok_or_else(|| BareError::RuntimeError(format!("Module path is not valid UTF-8: {}", path.display())))?;
+
+        let result = self.load_from(filename, None)?;
+        self.run()?;
+        Ok(result)
+    }
+
+    /// Run the bootstrap script `preload`, then the module at `main` as
+    /// `load_module` does, returning the module's exports.
+    ///
+    /// The preload runs to completion first, loop included, so globals it
+    /// defines or patches (such as `console`) are in place before `main`
+    /// starts. It shows up as `[preload]` in stack traces, and if it fails
+    /// `main` is never loaded.
+    pub fn run_file_with_preload<P: AsRef<Path>>(&self, preload: &str, main: P) -> BareResult<Value<'_>> {
+        self.eval_with_filename(preload, "[preload]")?;
+        self.load_module(main)
+    }
+
+    /// Load `source` as the module `filename` without running the event loop,
+    /// returning the result of loading it, e.g. the module's `module.exports`.
+    ///
+    /// This is the first half of `eval_with_filename`, mirroring the split
+    /// between `bare_load` and `bare_run`. The module's top-level code runs
+    /// here; timers, I/O callbacks and other work it schedules only run once
+    /// `run` (or `run_once`/`run_nowait`) drives the loop, so the exports can
+    /// be inspected or set up before that.
+    ///
+    /// Every load parses and compiles `source` afresh: libjs exposes no V8
+    /// code cache, so compiled code can't be saved and reused across runs.
+    ///
+    /// A `SyntaxError` is reported as `BareError::CompileError` only if
+    /// `source` doesn't compile; one thrown by its top-level code, such as
+    /// from `JSON.parse`, stays a `BareError::JSError`.
+    pub fn load(&self, source: &str, filename: &str) -> BareResult<Value<'_>> {
+        self.load_from(filename, Some(source))
+    }
+
+    /// Parse and compile `source` as the module `filename` without running
+    /// any of it, reporting a syntax error as `BareError::CompileError`.
+    ///
+    /// `bare_load` runs the module's top-level code as soon as it compiles, so
+    /// the source is compiled straight through libjs instead: as an ES module
+    /// for `.mjs` files, and otherwise as the body of a CommonJS module
+    /// function. Imports and requires are not resolved.
+    pub fn check_syntax(&self, source: &str, filename: &str) -> BareResult<()> {
+        let status = unsafe { self.compile(source, filename)? };
+        self.check_exception().map_err(|e| compile_error(e, filename))?;
+        if status != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to compile {}", filename)));
+        }
+        Ok(())
+    }
+
+    // Compile `source` as `check_syntax` describes, returning the libjs
+    // status. A syntax error is left pending as an exception.
+    unsafe fn compile(&self, source: &str, filename: &str) -> BareResult<i32> {
+        let c_filename = CString::new(filename)?;
+
+        if filename.ends_with(".mjs") {
+            let code = Value::new_string(self, source)?;
+            let mut module = ptr::null_mut();
+            let status = js_create_module(
+                self.env, c_filename.as_ptr(), filename.len(), 0, code.as_ptr(),
+                None, ptr::null_mut(), &mut module,
+            );
+            if status == 0 && js_delete_module(self.env, module) != 0 {
+                return Err(BareError::RuntimeError(format!("Failed to delete module {}", filename)));
+            }
+            Ok(status)
+        } else {
+            // A function body can't start with a hashbang, which bare allows
+            // in scripts, so comment it out
+            let code = match source.strip_prefix("#!") {
+                Some(rest) => Value::new_string(self, &format!("//{}", rest))?,
+                None => Value::new_string(self, source)?,
+            };
+            let mut args = Vec::new();
+            for name in ["exports", "require", "module", "__filename", "__dirname"] {
+                args.push(Value::new_string(self, name)?.as_ptr());
+            }
+            let mut function = ptr::null_mut();
+            Ok(js_create_function_with_source(
+                self.env, ptr::null(), 0, c_filename.as_ptr(), filename.len(),
+                args.as_ptr(), args.len(), 0, code.as_ptr(), &mut function,
+            ))
+        }
+    }
+
+    // Load `filename`, from `source` if given or from disk otherwise
+    fn load_from(&self, filename: &str, source: Option<&str>) -> BareResult<Value<'_>> {
+        let c_filename = CString::new(filename)?;
+        let script = source.map(CString::new).transpose()?;
+        let buf = script.as_ref().map(|script| uv_buf_t {
+            base: script.as_ptr() as *mut _,
+            len: script.as_bytes().len(),
+        });
+        let buf_ptr = buf.as_ref().map_or(ptr::null(), |buf| buf as *const uv_buf_t);
+
+        let mut result = ptr::null_mut();
+        self.metrics.borrow_mut().scripts_loaded += 1;
+        let status = self.timed(|| unsafe { bare_load(self.raw, c_filename.as_ptr(), buf_ptr, &mut result) });
+        if let Err(error) = self.check_exception() {
+            return Err(self.load_error(error, filename, source));
+        }
+        if status != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to load {}", filename)));
+        }
+
+        // Scripts that produce no value evaluate to undefined
+        if result.is_null() {
+            return Value::undefined(self.env);
+        }
+        Ok(unsafe { Value::from_raw(self.env, result) })
+    }
+
+    // A SyntaxError out of `bare_load` comes either from compiling the module
+    // or from its top-level code, like a failed `JSON.parse`. Compiling the
+    // source again on its own tells the two apart. JSON modules are parsed
+    // rather than compiled, so their errors are left as they are.
+    fn load_error(&self, error: BareError, filename: &str, source: Option<&str>) -> BareError {
+        if error.js_error_kind() != Some(JsErrorKind::SyntaxError) || filename.ends_with(".json") {
+            return error;
+        }
+        let source = match source {
+            Some(source) => source.to_string(),
+            None => match std::fs::read_to_string(filename) {
+                Ok(source) => source,
+                Err(_) => return error,
             },
-            BareError::MemoryError(msg) => write!(f, "Memory error: {}", msg),
-            BareError::ResourceExhausted(msg) => write!(f, "Resource exhausted: {}", msg),
+        };
+
+        let compiled = unsafe { self.compile(&source, filename) };
+        // The exception the check raises repeats the one already taken
+        let _ = unsafe { take_exception(self.env) };
+        match compiled {
+            Ok(0) | Err(_) => error,
+            Ok(_) => compile_error(error, filename),
+        }
+    }
+
+    /// Run the event loop until it has no more work or a script calls
+    /// `Bare.exit`, then check for a pending JS exception.
+    ///
+    /// A nonzero status from `bare_run` is an error even when no exception
+    /// is pending, so a failed run is never mistaken for success. bare only
+    /// fails a run that way on internal errors such as a failing `uv_run`,
+    /// which scripts can't provoke, so the test suite doesn't cover this path.
+    pub fn run(&self) -> BareResult<()> {
+        self.metrics.borrow_mut().scripts_run += 1;
+        let run_result = self.timed(|| unsafe { bare_run(self.raw) });
+        self.check_exception()?;
+
+        if run_result != 0 {
+            return Err(BareError::RuntimeError(format!("bare_run failed with code {}", run_result)));
+        }
+        Ok(())
+    }
+
+    /// Run a single iteration of the event loop, waiting for I/O if there is
+    /// none ready, and return whether there is still pending work.
+    ///
+    /// This drives the runtime's UV loop directly rather than through
+    /// `bare_run`, so embedders can interleave it with their own loop. Call it
+    /// only after the script has been loaded with `bare_load`: top-level code
+    /// runs during the load, and the loop only sees the handles it created.
+    /// Bare's `beforeExit` and `exit` events are not emitted; call `run` once
+    /// this returns `false` to let them fire.
+    pub fn run_once(&self) -> BareResult<bool> {
+        self.run_loop(uv_run_mode_UV_RUN_ONCE)
+    }
+
+    /// Like `run_once`, but return immediately if no I/O is ready
+    pub fn run_nowait(&self) -> BareResult<bool> {
+        self.run_loop(uv_run_mode_UV_RUN_NOWAIT)
+    }
+
+    /// Whether the event loop still has work that would keep `run` going,
+    /// such as pending timers, open sockets or in-flight requests.
+    ///
+    /// This is `uv_loop_alive`: it counts active, referenced handles (timers,
+    /// sockets, ...) and pending requests (file system operations, DNS
+    /// lookups, ...), but not unreferenced handles like an `unref`'d timer.
+    /// The loop belongs to the runtime, so work of other instances set up on
+    /// it counts too. A driver can call `run_once` until this is `false`.
+    pub fn has_pending_work(&self) -> bool {
+        unsafe { uv_loop_alive(self.uv_loop) != 0 }
+    }
+
+    fn run_loop(&self, mode: uv_run_mode) -> BareResult<bool> {
+        // uv_run returns nonzero while there are still active handles or requests
+        let alive = self.timed(|| unsafe { uv_run(self.uv_loop, mode) }) != 0;
+        self.check_exception()?;
+        Ok(alive)
+    }
+
+    /// Run all pending microtasks, such as the `.then` callbacks of promises
+    /// that have already settled.
+    ///
+    /// The event loop drains microtasks after every JS callback it makes, so
+    /// `run`, `run_once` and `run_nowait` need no help. Calls made directly
+    /// from Rust, like `Value::call`, don't: promises they settle only react
+    /// once this is called or the loop next runs JS.
+    pub fn drain_microtasks(&self) -> BareResult<()> {
+        unsafe {
+            // libjs only drains the queue after a call made with a checkpoint,
+            // so make one to a function that does nothing
+            let mut noop = ptr::null_mut();
+            if js_create_function(self.env, ptr::null(), 0, Some(noop_callback), ptr::null_mut(), &mut noop) != 0 {
+                return Err(BareError::RuntimeError("Failed to create microtask checkpoint function".into()));
+            }
+
+            let receiver = Value::undefined(self.env)?;
+            let mut result = ptr::null_mut();
+            if js_call_function_with_checkpoint(self.env, receiver.as_ptr(), noop, 0, ptr::null(), &mut result) != 0 {
+                self.check_exception()?;
+                return Err(BareError::RuntimeError("Failed to drain microtasks".into()));
+            }
+        }
+        self.check_exception()
+    }
+
+    /// Start `source` as a script on a new thread, with its own JS
+    /// environment and event loop.
+    ///
+    /// The worker runs alongside this instance without sharing any JS state;
+    /// use `Worker::join` to wait for it to finish.
+    pub fn spawn_worker(&self, source: &str) -> BareResult<Worker<'_>> {
+        let options = self.to_js(&serde_json::json!({ "source": source }))?;
+        unsafe { Worker::spawn(self.env, options) }
+    }
+
+    /// Run `f` on this instance's event loop once `delay` has elapsed,
+    /// interleaved with the script's own timers and I/O.
+    ///
+    /// The pending timer keeps the loop alive like a JS `setTimeout`, so `run`
+    /// waits for it. Timers that haven't fired when the instance is torn down
+    /// are cancelled. A panic in `f` is caught and logged.
+    pub fn set_timeout<F: FnOnce() + 'static>(&self, delay: Duration, f: F) -> BareResult<()> {
+        unsafe { self.timers.set_timeout(self.uv_loop, delay, Box::new(f)) }
+    }
+
+    /// Like `run`, but terminate execution once `timeout` has elapsed.
+    ///
+    /// A watchdog thread calls `bare_terminate` at the deadline, and the run
+    /// fails with `BareError::ResourceExhausted`. Only the event loop is
+    /// covered: top-level module code already ran during `bare_load`. The
+    /// instance is still torn down normally when it is dropped. Use
+    /// `drain_with_budget` rather than `run` for work left on the loop after
+    /// a timeout, since it may hang again.
+    pub fn run_with_timeout(&self, timeout: Duration) -> BareResult<()> {
+        let (result, timed_out) = self.with_watchdog(timeout, || self.run());
+
+        if timed_out {
+            return Err(BareError::ResourceExhausted(format!("script timeout after {:?}", timeout)));
+        }
+        result
+    }
+
+    /// Run what is ready on the event loop, at most `max_iterations` times
+    /// and for at most `timeout`, and return whether the loop quiesced.
+    ///
+    /// Each iteration drains the microtask queue and then runs the loop once
+    /// without waiting for I/O, so due timers and ready callbacks run but the
+    /// loop is never blocked on. JS still running at the deadline is
+    /// terminated as in `run_with_timeout`, so a promise that keeps queueing
+    /// microtasks can't hang the drain. Returns `false` if the loop still had
+    /// work when the budget ran out.
+    pub fn drain_with_budget(&self, max_iterations: usize, timeout: Duration) -> BareResult<bool> {
+        let deadline = Instant::now() + timeout;
+
+        let (result, timed_out) = self.with_watchdog(timeout, || {
+            for _ in 0..max_iterations {
+                self.drain_microtasks()?;
+                if !self.run_nowait()? {
+                    return Ok(true);
+                }
+                if Instant::now() >= deadline {
+                    break;
+                }
+            }
+            Ok(false)
+        });
+
+        // The error is the termination of the script, which is expected here
+        if timed_out {
+            return Ok(false);
+        }
+        result
+    }
+
+    // Run `f`, calling `bare_terminate` from a watchdog thread if it takes
+    // longer than `timeout`. Returns the result of `f` and whether it was
+    // terminated.
+    fn with_watchdog<T>(&self, timeout: Duration, f: impl FnOnce() -> T) -> (T, bool) {
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let timed_out = Arc::new(AtomicBool::new(false));
+
+        // bare_terminate is safe to call from any thread
+        let bare = self.raw as usize;
+        let flag = timed_out.clone();
+        let watchdog = thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                flag.store(true, Ordering::SeqCst);
+                unsafe { bare_terminate(bare as *mut bare_t) };
+            }
+        });
+
+        let result = f();
+        let _ = done_tx.send(());
+        let _ = watchdog.join();
+
+        (result, timed_out.load(Ordering::SeqCst))
+    }
+
+    /// Convert a JSON value into a JS value in this instance's environment.
+    ///
+    /// Numbers become JS doubles, so integers beyond 2^53 lose precision.
+    pub fn to_js(&self, json: &serde_json::Value) -> BareResult<Value<'_>> {
+        unsafe { json::to_js(self.env, json) }
+    }
+
+    /// Call the global function `name` with `args`, and convert its result
+    /// to JSON, as `Value::to_json` does.
+    ///
+    /// Arguments are built with `into()` from numbers, strings, booleans,
+    /// JSON or values, e.g. `&[json!({ "id": 1 }).into(), 0.5.into()]`.
+    /// A global that isn't a function is a `TypeError`, and an exception the
+    /// function throws is returned as a `JSError`. Returned promises are not
+    /// awaited, so async functions can't be called this way.
+    pub fn call_global(&self, name: &str, args: &[JsArg]) -> BareResult<serde_json::Value> {
+        let function = self.get_global(name)?;
+        if !matches!(function.type_of()?, ValueType::Function) {
+            return Err(value::type_error(format!("Global '{}' is not a function", name)));
+        }
+        function.call_args(args)?.to_json()
+    }
+
+    /// Capture everything scripts write through `console` from now on.
+    ///
+    /// The returned handle drains the captured text; output no longer reaches
+    /// the process stdout/stderr while capture is active.
+    pub fn capture_output(&self) -> BareResult<OutputCapture> {
+        let capture = OutputCapture::default();
+
+        let sink = capture.clone();
+        let env = self.env;
+        self.set_global_fn(console::CAPTURE_FN, move |args| {
+            if let Some(text) = args.first() {
+                sink.push(&text.as_string()?);
+            }
+            Value::undefined(env)
+        })?;
+
+        self.run_in_global_scope("bare-rs:capture", console::CAPTURE_SHIM)?;
+        Ok(capture)
+    }
+
+    // Evaluate `source` as a classic script in the global scope, bypassing
+    // the module system. Used to install internal shims.
+    fn run_in_global_scope(&self, filename: &str, source: &str) -> BareResult<Value<'_>> {
+        let c_filename = CString::new(filename)?;
+
+        unsafe {
+            let mut script = ptr::null_mut();
+            if js_create_string_utf8(self.env, source.as_ptr(), source.len(), &mut script) != 0 {
+                return Err(BareError::RuntimeError("Failed to create script source".into()));
+            }
+
+            let mut result = ptr::null_mut();
+            if js_run_script(self.env, c_filename.as_ptr(), filename.len(), 0, script, &mut result) != 0 {
+                self.check_exception()?;
+                return Err(BareError::RuntimeError(format!("Failed to run {}", filename)));
+            }
+
+            Ok(Value::from_raw(self.env, result))
+        }
+    }
+
+    /// Register `value` as a virtual module named `name`, so that scripts can
+    /// `require(name)` it, e.g. to inject configuration without templating.
+    ///
+    /// The module is served as JSON through bare's module system, so every
+    /// script gets the same cached object. Registering a name again only
+    /// affects modules that haven't required it yet.
+    pub fn import_json_module(&self, name: &str, value: &serde_json::Value) -> BareResult<()> {
+        modules::import_json(self, name, value)
+    }
+
+    /// Resolve `require` and `import` specifiers through `resolver` before
+    /// bare's own resolution, to serve modules from memory, a database or a
+    /// bundle rather than the filesystem.
+    ///
+    /// `resolver` gets the specifier and the URL of the requiring module, and
+    /// returns the CommonJS source of the module, or `None` to fall back to
+    /// the usual resolution. Modules are identified and cached by specifier.
+    /// An error is thrown to the requiring script. Setting a resolver again
+    /// replaces the previous one.
+    pub fn set_module_resolver<F>(&self, resolver: F) -> BareResult<()>
+    where
+        F: Fn(&str, &str) -> BareResult<Option<String>> + 'static,
+    {
+        let env = self.env;
+        let callback: Box<NativeFn> = Box::new(Box::new(move |args: &[Value]| {
+            let specifier = args[0].as_string()?;
+            let referrer = args[1].as_string()?;
+            match resolver(&specifier, &referrer)? {
+                Some(source) => unsafe { json::to_js(env, &serde_json::Value::String(source)) },
+                None => Value::undefined(env),
+            }
+        }));
+
+        let function = unsafe {
+            Value::from_raw(self.env, function::create_function(self.env, "resolveModule", &callback)?)
+        };
+        self.functions.borrow_mut().push(callback);
+        modules::set_resolver(self, function)
+    }
+
+    /// Run `f` when the event loop is about to exit, like `Bare.on('beforeExit')`
+    pub fn on_before_exit<F: FnMut() + 'static>(&self, f: F) -> BareResult<()> {
+        if self.events.before_exit.borrow().is_none()
+            && unsafe { bare_on_before_exit(self.raw, Some(events::on_before_exit)) } != 0
+        {
+            return Err(BareError::RuntimeError("Failed to register beforeExit handler".into()));
+        }
+        *self.events.before_exit.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Run `f` with the exit code when the instance exits, like `Bare.on('exit')`
+    pub fn on_exit<F: FnMut(i32) + 'static>(&self, f: F) -> BareResult<()> {
+        // The exit callback itself is installed by `Runtime::setup`
+        *self.events.exit.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// The code the instance exited with, e.g. through `Bare.exit(5)`, or
+    /// `None` if it hasn't exited yet.
+    ///
+    /// `Bare.exit` never ends the host process: bare emits `exit`, stops the
+    /// loop and terminates the running script, so `run` returns to Rust with
+    /// pending work abandoned and the code recorded here.
+    ///
+    /// The code is recorded by bare-rs's exit callback, so it stays `None` if
+    /// that callback is replaced through the raw `bare_on_exit` binding.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.events.exit_code.get()
+    }
+
+    /// Run `f` when the event loop becomes idle, like `Bare.on('idle')`
+    pub fn on_idle<F: FnMut() + 'static>(&self, f: F) -> BareResult<()> {
+        if self.events.idle.borrow().is_none()
+            && unsafe { bare_on_idle(self.raw, Some(events::on_idle)) } != 0
+        {
+            return Err(BareError::RuntimeError("Failed to register idle handler".into()));
+        }
+        *self.events.idle.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Run `f` with the linger value when the instance is suspended, like
+    /// `Bare.on('suspend')`
+    pub fn on_suspend<F: FnMut(i32) + 'static>(&self, f: F) -> BareResult<()> {
+        if self.events.suspend.borrow().is_none()
+            && unsafe { bare_on_suspend(self.raw, Some(events::on_suspend)) } != 0
+        {
+            return Err(BareError::RuntimeError("Failed to register suspend handler".into()));
+        }
+        *self.events.suspend.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Run `f` when the instance resumes after a suspend, like `Bare.on('resume')`
+    pub fn on_resume<F: FnMut() + 'static>(&self, f: F) -> BareResult<()> {
+        if self.events.resume.borrow().is_none()
+            && unsafe { bare_on_resume(self.raw, Some(events::on_resume)) } != 0
+        {
+            return Err(BareError::RuntimeError("Failed to register resume handler".into()));
+        }
+        *self.events.resume.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Run `f` when the instance is torn down, like `Bare.on('teardown')`.
+    ///
+    /// Teardown happens exactly once per instance, through `teardown` or when
+    /// the `Bare` is dropped, so `f` runs at most once.
+    pub fn on_teardown<F: FnMut() + 'static>(&self, f: F) -> BareResult<()> {
+        if self.events.teardown.borrow().is_none()
+            && unsafe { bare_on_teardown(self.raw, Some(events::on_teardown)) } != 0
+        {
+            return Err(BareError::RuntimeError("Failed to register teardown handler".into()));
+        }
+        *self.events.teardown.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Stop the CPU profiler and return the profile collected since setup, or
+    /// since the previous call, as `.cpuprofile` JSON. Profiling then resumes.
+    ///
+    /// Only available when the runtime was created with
+    /// `PlatformOptions::sampling_profiler`. Profiles are collected per
+    /// instance, so scripts on other instances don't show up in them.
+    pub fn take_profile(&self) -> BareResult<String> {
+        match &self.profiler {
+            Some(profiler) => profiler.take_profile(),
+            None => Err(BareError::RuntimeError(
+                "Sampling profiler is not enabled; see PlatformOptions::sampling_profiler".into()
+            )),
+        }
+    }
+
+    /// WebSocket URL that DevTools can attach to this instance's inspector
+    /// on, or `None` unless the runtime was created with
+    /// `PlatformOptions::inspector`
+    pub fn inspector_url(&self) -> Option<String> {
+        #[cfg(feature = "inspector")]
+        return self.inspector.as_ref().map(Inspector::url);
+        #[cfg(not(feature = "inspector"))]
+        None
+    }
+
+    /// Block until a debugger has attached to the inspector and asked the
+    /// script to run, like `node --inspect-brk`, then break on the first
+    /// line of `filename` once it is loaded.
+    ///
+    /// Scripts are matched by file name, since V8 only knows them by URL.
+    ///
+    /// Without the `inspector` feature there is never a debugger to wait for,
+    /// so this always fails.
+    pub fn wait_for_debugger(&self, filename: &str) -> BareResult<()> {
+        #[cfg(feature = "inspector")]
+        if let Some(inspector) = &self.inspector {
+            return inspector.wait_for_debugger(filename);
+        }
+        #[cfg(not(feature = "inspector"))]
+        let _ = filename;
+        Err(BareError::RuntimeError(
+            "Inspector is not enabled; see PlatformOptions::inspector".into()
+        ))
+    }
+
+    /// Run a full garbage collection on this instance's heap, for example to
+    /// measure steady-state memory between runs.
+    ///
+    /// Only available when the runtime was created with
+    /// `PlatformOptions::expose_gc`, which also gives scripts `gc()`.
+    pub fn collect_garbage(&self) -> BareResult<()> {
+        if !self.gc_exposed {
+            return Err(BareError::RuntimeError(
+                "Garbage collection is not exposed; see PlatformOptions::expose_gc".into()
+            ));
+        }
+        if unsafe { js_request_garbage_collection(self.env) } != 0 {
+            return Err(BareError::RuntimeError("Failed to request garbage collection".into()));
+        }
+        Ok(())
+    }
+
+    /// Snapshot of the instance's counters: scripts loaded and run, JS
+    /// exceptions by type and time spent in JS, for example to export with
+    /// `RuntimeMetrics::to_prometheus`
+    pub fn metrics(&self) -> RuntimeMetrics {
+        self.metrics.borrow().clone()
+    }
+
+    // Run `f`, adding the time it takes to the run time metric
+    fn timed<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.metrics.borrow_mut().run_time += start.elapsed();
+        result
+    }
+
+    /// Current heap usage of this instance, for example to chart memory
+    /// against the configured `memory_limit`
+    pub fn heap_stats(&self) -> BareResult<HeapStats> {
+        // Version 1 includes `external_memory`
+        let mut stats = js_heap_statistics_t {
+            version: 1,
+            total_heap_size: 0,
+            used_heap_size: 0,
+            external_memory: 0,
+        };
+        if unsafe { js_get_heap_statistics(self.env, &mut stats) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get heap statistics".into()));
+        }
+
+        Ok(HeapStats {
+            total_heap_size: stats.total_heap_size,
+            used_heap_size: stats.used_heap_size,
+            external_memory: stats.external_memory,
+            heap_size_limit: Some(self.memory_limit).filter(|&limit| limit != 0),
+        })
+    }
+
+    /// Call `f` with the thrown value when an exception escapes to the event
+    /// loop. Returning `true` marks it handled and execution continues;
+    /// returning `false` terminates the instance, and the exception is
+    /// returned as an error from `run` or `check_exception`.
+    ///
+    /// This replaces bare's own handling, so `Bare.on('uncaughtException')`
+    /// listeners no longer fire.
+    pub fn set_uncaught_exception_handler<F>(&self, f: F) -> BareResult<()>
+    where
+        F: FnMut(Value) -> bool + 'static,
+    {
+        let handlers = &*self.events as *const EventHandlers as *mut std::os::raw::c_void;
+        if unsafe { js_on_uncaught_exception(self.env, Some(events::on_uncaught_exception), handlers) } != 0 {
+            return Err(BareError::RuntimeError("Failed to register uncaught exception handler".into()));
+        }
+        *self.events.uncaught_exception.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Call `f` with the rejection reason when a promise is rejected and no
+    /// handler is attached by the end of the microtask checkpoint.
+    ///
+    /// This replaces bare's own handling, so `Bare.on('unhandledRejection')`
+    /// listeners no longer fire and the rejection no longer ends the
+    /// instance: reporting or failing is up to `f`.
+    pub fn on_unhandled_rejection<F: FnMut(Value) + 'static>(&self, f: F) -> BareResult<()> {
+        let handlers = &*self.events as *const EventHandlers as *mut std::os::raw::c_void;
+        if unsafe { js_on_unhandled_rejection(self.env, Some(events::on_unhandled_rejection), handlers) } != 0 {
+            return Err(BareError::RuntimeError("Failed to register unhandled rejection handler".into()));
+        }
+        *self.events.unhandled_rejection.borrow_mut() = Some(Box::new(f));
+        Ok(())
+    }
+
+    /// Shut the instance down gracefully with `exit_code`, letting scripts
+    /// clean up: `beforeExit` is emitted, then `Bare.exit` runs the `exit`
+    /// handlers, and the loop runs until the instance stops.
+    ///
+    /// This is the graceful counterpart of `teardown`, which releases the
+    /// instance without giving pending work a chance to finish. Call
+    /// `shutdown` first when scripts flush logs or close resources in those
+    /// handlers; it does nothing if the instance has already exited.
+    pub fn shutdown(&self, exit_code: i32) -> BareResult<()> {
+        if self.exit_code().is_some() {
+            return Ok(());
+        }
+
+        let process = self.get_global("Bare")?;
+        process.get_property("emit")?.call_with_this(process, &[
+            Value::new_string(self, "beforeExit")?,
+            Value::new_number(self, exit_code as f64)?,
+        ])?;
+
+        match &self.sandbox {
+            // Scripts lost `Bare.exit` to the sandbox, but it was kept for this
+            Some(sandbox) => {
+                sandbox.exit(process, Value::new_number(self, exit_code as f64)?)?;
+                self.run()?;
+            }
+            None => {
+                self.eval_with_filename(&format!("Bare.exit({})", exit_code), "[shutdown]")?;
+            }
         }
+        Ok(())
+    }
+
+    /// Tear down the instance, returning the exit code of the process.
+    ///
+    /// This is the hard stop: pending work is abandoned. See `shutdown` for
+    /// running the script's cleanup handlers first.
+    pub fn teardown(mut self) -> BareResult<i32> {
+        self.teardown_once()
+    }
+
+    fn teardown_once(&mut self) -> BareResult<i32> {
+        if self.torn_down {
+            return Err(BareError::RuntimeError("Bare runtime already torn down".into()));
+        }
+        self.torn_down = true;
+
+        // The inspector sessions have to go before the environment does
+        self.profiler = None;
+        #[cfg(feature = "inspector")]
+        {
+            self.inspector = None;
+        }
+        // So does the reference the sandbox holds
+        self.sandbox = None;
+
+        // Timers run Rust closures that may refer to this instance, so they
+        // must not fire once it is gone. Bare's teardown runs the loop, which
+        // frees them.
+        self.timers.close_all();
+
+        let mut exit_code = 0;
+        let teardown_result = unsafe { bare_teardown(self.raw, &mut exit_code) };
+
+        // Teardown emits the exit event, so handlers stay reachable until here
+        events::unregister(self.raw);
+        metrics::unregister(self.env);
+
+        if teardown_result != 0 {
+            // The loop may still have handles open, so leak it rather than
+            // delete it from under them
+            return Err(BareError::RuntimeError("Failed to teardown Bare runtime".into()));
+        }
+        if self.owns_loop {
+            unsafe { uv_loop_delete(self.uv_loop) };
+        }
+        Ok(exit_code)
     }
 }
 
-impl std::error::Error for BareError {}
+unsafe extern "C" fn noop_callback(_env: *mut js_env_t, _info: *mut js_callback_info_t) -> *mut js_value_t {
+    ptr::null_mut()
+}
 
-// Add conversion from NulError to BareError
-impl From<NulError> for BareError {
-    fn from(error: NulError) -> Self {
-        BareError::RuntimeError(format!("String contains null byte: {}", error))
+impl fmt::Debug for Bare<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Bare")
+            .field("has_env", &!self.env.is_null())
+            .field("torn_down", &self.torn_down)
+            .field("exit_code", &self.exit_code())
+            .field("native_functions", &self.functions.borrow().len())
+            .finish()
     }
 }
 
-pub type BareResult<T> = Result<T, BareError>;
+impl Drop for Bare<'_> {
+    fn drop(&mut self) {
+        if !self.torn_down {
+            if let Err(e) = self.teardown_once() {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
 
-pub struct GlobalRuntime {
-    pub uv_loop: *mut uv_loop_t,
-    pub platform: *mut js_platform_t,
+/// Options for creating the JS platform of a `Runtime`
+#[derive(Debug, Clone, Copy)]
+pub struct PlatformOptions {
+    expose_gc: bool,
+    trace_gc: bool,
+    disable_optimizing_compiler: bool,
+    trace_optimizations: bool,
+    trace_deoptimizations: bool,
+    sampling_profiler_interval: Option<i32>,
+    optimize_for_memory: bool,
+    #[cfg(feature = "inspector")]
+    inspector_addr: Option<SocketAddr>,
+    abort_diagnostic: bool,
 }
 
-// Mark GlobalRuntime as thread safe since we control access through Mutex
-unsafe impl Send for GlobalRuntime {}
-unsafe impl Sync for GlobalRuntime {}
+impl PlatformOptions {
+    /// Options with the defaults: everything off except `optimize_for_memory`
+    pub fn new() -> Self {
+        PlatformOptions {
+            expose_gc: false,
+            trace_gc: false,
+            disable_optimizing_compiler: false,
+            trace_optimizations: false,
+            trace_deoptimizations: false,
+            sampling_profiler_interval: None,
+            optimize_for_memory: true,
+            #[cfg(feature = "inspector")]
+            inspector_addr: None,
+            abort_diagnostic: false,
+        }
+    }
+
+    /// Expose the garbage collection APIs, such as `gc()`, to scripts, and
+    /// enable `Bare::collect_garbage`
+    pub fn expose_gc(mut self, enabled: bool) -> Self {
+        self.expose_gc = enabled;
+        self
+    }
+
+    /// Install a process-wide SIGABRT handler that writes a line to stderr
+    /// when the process aborts, pointing at the fatal error V8 prints just
+    /// before it aborts (such as running out of heap).
+    ///
+    /// This is only a diagnostic: a fatal engine error can't be recovered
+    /// from or turned into a `BareError`, and the process still aborts. The
+    /// handler that was installed before, such as a crash reporter's, is
+    /// called afterwards. Off by default, as the handler is shared by the
+    /// whole process; applications rather than libraries should enable it.
+    pub fn abort_diagnostic(mut self, enabled: bool) -> Self {
+        self.abort_diagnostic = enabled;
+        self
+    }
+
+    /// Trace invocations of the garbage collector
+    pub fn trace_gc(mut self, enabled: bool) -> Self {
+        self.trace_gc = enabled;
+        self
+    }
+
+    /// Disable the optimizing compiler
+    pub fn disable_optimizing_compiler(mut self, disabled: bool) -> Self {
+        self.disable_optimizing_compiler = disabled;
+        self
+    }
+
+    /// Trace optimizations made by the optimizing compiler
+    pub fn trace_optimizations(mut self, enabled: bool) -> Self {
+        self.trace_optimizations = enabled;
+        self
+    }
+
+    /// Trace deoptimizations made by the optimizing compiler
+    pub fn trace_deoptimizations(mut self, enabled: bool) -> Self {
+        self.trace_deoptimizations = enabled;
+        self
+    }
+
+    /// Enable the sampling profiler, taking a stack trace every
+    /// `interval_us` microseconds. Profiles are read with `Bare::take_profile`.
+    pub fn sampling_profiler(mut self, interval_us: i32) -> Self {
+        self.sampling_profiler_interval = Some(interval_us);
+        self
+    }
+
+    /// Serve the inspector of each instance on `addr` for Chrome DevTools to
+    /// attach to. Port 0 picks a free port, which `Bare::inspector_url`
+    /// reports; with a fixed port only one instance can be set up at a time.
+    #[cfg(feature = "inspector")]
+    pub fn inspector(mut self, addr: SocketAddr) -> Self {
+        self.inspector_addr = Some(addr);
+        self
+    }
+
+    /// Trade performance for memory where supported
+    pub fn optimize_for_memory(mut self, enabled: bool) -> Self {
+        self.optimize_for_memory = enabled;
+        self
+    }
+
+    /// Produce the FFI options struct passed to `js_create_platform`
+    pub fn build(&self) -> js_platform_options_t {
+        js_platform_options_t {
+            version: 1,
+            expose_garbage_collection: self.expose_gc,
+            trace_garbage_collection: self.trace_gc,
+            disable_optimizing_compiler: self.disable_optimizing_compiler,
+            trace_optimizations: self.trace_optimizations,
+            trace_deoptimizations: self.trace_deoptimizations,
+            enable_sampling_profiler: self.sampling_profiler_interval.is_some(),
+            sampling_profiler_interval: self.sampling_profiler_interval.unwrap_or(0),
+            optimize_for_memory: self.optimize_for_memory,
+        }
+    }
+}
+
+impl Default for PlatformOptions {
+    fn default() -> Self {
+        PlatformOptions::new()
+    }
+}
 
-/// Enhanced exception handler
+/// Return the pending JS exception, if any, as a `BareError`, clearing it
+/// and logging it at error level.
+///
+/// Callers that report the returned error themselves should use
+/// `try_handle_js_exception`, which doesn't log, to avoid reporting it twice.
 pub unsafe fn handle_js_exception(env: *mut js_env_t) -> BareResult<()> {
-    log::debug!("Checking for exception...");
+    let result = try_handle_js_exception(env);
+    if let Err(error) = &result {
+        log::error!("JavaScript error: {}", error);
+    }
+    result
+}
+
+/// Like `handle_js_exception`, without logging the exception, leaving that
+/// to the caller
+pub unsafe fn try_handle_js_exception(env: *mut js_env_t) -> BareResult<()> {
+    match take_exception(env)? {
+        Some(error) => Err(error_from_value(env, error)?),
+        None => Ok(()),
+    }
+}
+
+/// Like `handle_js_exception`, but return the pending exception as a single
+/// JSON line (`{"type":...,"message":...,"stack":...}`) for structured log
+/// pipelines instead of logging it as free text. The exception is cleared.
+///
+/// With no exception pending there is nothing to report, which is a
+/// `BareError::RuntimeError`; check `js_is_exception_pending` first where
+/// that is expected.
+pub unsafe fn handle_js_exception_json(env: *mut js_env_t) -> BareResult<String> {
+    let error = take_exception(env)?
+        .ok_or_else(|| BareError::RuntimeError("No JS exception is pending".into()))?;
+
+    let error_type = get_error_type(env, error)?;
+    metrics::record_exception(env, &error_type);
+    let json = serde_json::json!({
+        "type": error_type,
+        "message": get_error_message(env, error)?,
+        "stack": get_error_stack(env, error)?,
+    });
+    Ok(json.to_string())
+}
+
+// Get and clear the pending exception, if any
+unsafe fn take_exception(env: *mut js_env_t) -> BareResult<Option<*mut js_value_t>> {
+    trace_ffi!("Checking for exception...");
     let mut has_exception = false;
     let check_result = js_is_exception_pending(env, &mut has_exception);
-    log::debug!("Check result: {}, has_exception: {}", check_result, has_exception);
+    trace_ffi!("Check result: {}, has_exception: {}", check_result, has_exception);
 
     if check_result != 0 {
         log::error!("Failed to check exception status");
@@ -83,169 +1630,207 @@ pub unsafe fn handle_js_exception(env: *mut js_env_t) -> BareResult<()> {
     }
 
     if !has_exception {
-        log::debug!("No exception found");
-        return Ok(());
+        trace_ffi!("No exception found");
+        return Ok(None);
     }
 
-    log::debug!("Exception found, getting details...");
+    trace_ffi!("Exception found, getting details...");
     // Get the exception object
     let mut error = ptr::null_mut();
     let clear_result = js_get_and_clear_last_exception(env, &mut error);
-    log::debug!("Clear result: {}, error ptr: {:?}", clear_result, error);
+    trace_ffi!("Clear result: {}", clear_result);
 
     if clear_result != 0 {
         log::error!("Failed to get exception details");
         return Err(BareError::RuntimeError("Failed to get exception details".into()));
     }
+    trace_ffi!("Exception: {:?}", Value::from_raw(env, error));
 
+    Ok(Some(error))
+}
+
+/// Convert a thrown JS value into the `BareError` describing it
+pub(crate) unsafe fn error_from_value(env: *mut js_env_t, error: *mut js_value_t) -> BareResult<BareError> {
     // Extract error details
-    log::debug!("Getting error type...");
+    trace_ffi!("Getting error type...");
     let error_type = get_error_type(env, error)?;
-    log::debug!("Getting error message...");
+    trace_ffi!("Getting error message...");
     let message = get_error_message(env, error)?;
-    log::debug!("Getting error stack...");
+    trace_ffi!("Getting error stack...");
     let stack = get_error_stack(env, error)?;
+    metrics::record_exception(env, &error_type);
 
-    log::error!("JavaScript error:");
-    log::error!("  Type: {}", error_type);
-    log::error!("  Message: {}", message);
-    log::error!("  Stack: {}", stack);
+    // V8 reports allocations it can't satisfy as a catchable RangeError.
+    // Running out of JS heap is fatal to the whole process instead, and libjs
+    // exposes no near-heap-limit callback to intercept it with.
+    if error_type == "RangeError" && message.contains("allocation failed") {
+        return Ok(BareError::ResourceExhausted(format!("JS allocation failed: {}", message)));
+    }
 
-    Err(BareError::JSError {
+    Ok(BareError::JSError {
         error_type,
         message,
         stack: Some(stack),
     })
 }
 
-/// Helper functions for error details extraction
-pub unsafe fn get_error_type(env: *mut js_env_t, error: *mut js_value_t) -> BareResult<String> {
-    let mut constructor = ptr::null_mut();
-    let mut str_len = 0;
+// Report a SyntaxError from source that didn't compile. V8 reports its position through the message object, which libjs doesn't
+// expose, so the position is only known if a frame of the stack points into
+// the file itself.
+fn compile_error(error: BareError, filename: &str) -> BareError {
+    let frame = error.stack_frames().into_iter()
+        .find(|frame| frame.file.ends_with(filename));
 
-    // Convert constructor name to string
-    if js_get_value_string_utf8(env, constructor, ptr::null_mut(), 0, &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get constructor string length".into())); 
+    match error {
+        BareError::JSError { error_type, message, .. } if error_type == "SyntaxError" => {
+            BareError::CompileError {
+                message,
+                file: filename.to_string(),
+                line: frame.as_ref().map(|frame| frame.line),
+                column: frame.as_ref().map(|frame| frame.column),
+            }
+        }
+        error => error,
     }
+}
 
-    let mut buffer = vec![0u8; str_len as usize + 1];
-    if js_get_value_string_utf8(env, constructor, buffer.as_mut_ptr() as *mut u8, buffer.len(), &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get constructor string".into()));
+/// Helper functions for error details extraction.
+///
+/// Scripts may throw any value, not just errors, so these fall back to
+/// `typeof` for the type, `String(value)` for the message and an empty stack
+/// rather than failing on primitives or objects without those properties.
+pub unsafe fn get_error_type(env: *mut js_env_t, error: *mut js_value_t) -> BareResult<String> {
+    let value = Value::from_raw(env, error);
+    let name = error_property(value, "constructor")
+        .and_then(|constructor| error_string(constructor, "name"));
+    match name {
+        Some(name) => Ok(name),
+        None => Ok(value.type_of()?.as_str().to_string()),
     }
-
-    Ok(String::from_utf8_lossy(&buffer[..str_len as usize]).into_owned())
 }
 
 pub unsafe fn get_error_message(env: *mut js_env_t, error: *mut js_value_t) -> BareResult<String> {
-    let mut message = ptr::null_mut();
-    let mut str_len = 0;
-
-    // Get message property
-    if js_get_named_property(env, error, "message\0".as_ptr() as *const i8, &mut message) != 0 {
-        return Err(BareError::RuntimeError("Failed to get error message".into()));
-    }
-
-    // Convert message to string
-    if js_get_value_string_utf8(env, message, ptr::null_mut(), 0, &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get message string length".into()));
+    let value = Value::from_raw(env, error);
+    if let Some(message) = error_string(value, "message") {
+        return Ok(message);
     }
 
-    let mut buffer = vec![0u8; str_len as usize + 1];
-    if js_get_value_string_utf8(env, message, buffer.as_mut_ptr() as *mut u8, buffer.len(), &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get message string".into()));
+    let mut string = ptr::null_mut();
+    if js_coerce_to_string(env, error, &mut string) != 0 {
+        // Symbols and objects without a prototype can't be converted
+        take_exception(env)?;
+        return Ok(format!("[{}]", value.type_of()?));
     }
-
-    Ok(String::from_utf8_lossy(&buffer[..str_len as usize]).into_owned())
+    Value::from_raw(env, string).as_string()
 }
 
 pub unsafe fn get_error_stack(env: *mut js_env_t, error: *mut js_value_t) -> BareResult<String> {
-    let mut stack = ptr::null_mut();
-    let mut str_len = 0;
+    Ok(error_string(Value::from_raw(env, error), "stack").unwrap_or_default())
+}
 
-    // Get stack property
-    if js_get_named_property(env, error, "stack\0".as_ptr() as *const i8, &mut stack) != 0 {
-        return Err(BareError::RuntimeError("Failed to get error stack".into()));
+// Property `name` of a thrown value, or `None` if the value isn't an object.
+// A throwing getter counts as a missing property, its exception discarded.
+unsafe fn error_property<'a>(value: Value<'a>, name: &str) -> Option<Value<'a>> {
+    if !matches!(value.type_of().ok()?, ValueType::Object | ValueType::Function) {
+        return None;
     }
+    match value.get_property(name) {
+        Ok(property) => Some(property),
+        Err(_) => {
+            let _ = take_exception(value.env_ptr());
+            None
+        }
+    }
+}
 
-    // Convert stack to string
-    if js_get_value_string_utf8(env, stack, ptr::null_mut(), 0, &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get stack string length".into()));
+// Like `error_property`, for properties that are strings
+unsafe fn error_string(value: Value, name: &str) -> Option<String> {
+    let property = error_property(value, name)?;
+    if property.type_of().ok()? != ValueType::String {
+        return None;
     }
+    property.as_string().ok()
+}
 
-    let mut buffer = vec![0u8; str_len as usize + 1];
-    if js_get_value_string_utf8(env, stack, buffer.as_mut_ptr() as *mut u8, buffer.len(), &mut str_len) != 0 {
-        return Err(BareError::RuntimeError("Failed to get stack string".into()));
+/// Stack size `bare-rs` gives the thread the runtime runs on
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// V8 assumes about 1MB of stack of its own, so anything smaller than this
+// would overflow before V8 notices and crash instead of throwing a RangeError
+const MIN_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Run `f`, which should create and use the runtime, on a new thread with a
+/// stack of `stack_size` bytes, returning its result.
+///
+/// The main thread's stack size is fixed by the OS before `main` runs, so the
+/// only way to give the engine more room is a new thread. V8 still bounds JS
+/// recursion with its own limit; the larger stack keeps deeply nested native
+/// frames from overflowing before V8 notices.
+pub fn run_with_stack_size<T, F>(stack_size: usize, f: F) -> BareResult<T>
+where
+    F: FnOnce() -> BareResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    if stack_size < MIN_STACK_SIZE {
+        return Err(BareError::SetupError(format!(
+            "Stack size of {} bytes is too small, the minimum is {} bytes", stack_size, MIN_STACK_SIZE
+        )));
     }
 
-    Ok(String::from_utf8_lossy(&buffer[..str_len as usize]).into_owned())
-}
+    let handle = thread::Builder::new()
+        .name("bare-rs".into())
+        .stack_size(stack_size)
+        .spawn(f)
+        .map_err(|e| BareError::SetupError(format!("Failed to spawn runtime thread: {}", e)))?;
 
-#[cfg(target_os = "macos")]
-pub fn set_stack_size() -> BareResult<()> {
-    // Only set stack size when running as main executable
-    if std::env::args().next().map_or(false, |arg| arg.ends_with("bare-rs")) {
-        unsafe {
-            let mut attr: libc::pthread_attr_t = std::mem::zeroed();
-            if libc::pthread_attr_init(&mut attr) != 0 {
-                return Err(BareError::SetupError("Failed to init pthread attr".into()));
-            }
-            
-            // Set stack size to 64MB
-            if libc::pthread_attr_setstacksize(&mut attr, 64 * 1024 * 1024) != 0 {
-                return Err(BareError::SetupError("Failed to set stack size".into()));
-            }
-            
-            if libc::pthread_attr_destroy(&mut attr) != 0 {
-                return Err(BareError::SetupError("Failed to destroy pthread attr".into()));
-            }
-        }
+    match handle.join() {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
     }
-    Ok(())
 }
 
-#[cfg(not(target_os = "macos"))]
+/// Formerly meant to enlarge the main thread's stack on macOS, which can't
+/// be done once the thread is running, so it never had an effect. Kept so
+/// existing callers still build; it does nothing.
+#[deprecated(note = "use run_with_stack_size")]
 pub fn set_stack_size() -> BareResult<()> {
     Ok(())
 }
 
+// The singleton is only ever replaced whole, so a panic while the lock was
+// held can't have left it half-written, and a poisoned lock is safe to take
+// over
+fn lock_runtime() -> std::sync::MutexGuard<'static, Option<GlobalRuntime>> {
+    RUNTIME.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Initialize the process-wide runtime used by `get_runtime`.
+///
+/// Kept for backward compatibility; new code should prefer `Runtime::new`,
+/// which returns an owned handle instead of a global singleton. Both draw on
+/// the same single JS platform, so a process can use one or the other.
 pub unsafe fn init_runtime_once() -> BareResult<()> {
-    let mut runtime = RUNTIME.lock().unwrap();
+    let mut runtime = lock_runtime();
     if runtime.is_none() {
-        // Initialize UV loop first
-        let uv_loop = uv_loop_new();
-        if uv_loop.is_null() {
-            return Err(BareError::RuntimeError("Failed to create UV loop".into()));
-        }
-
-        // Initialize JS platform
-        let mut platform = ptr::null_mut();
-        let mut platform_options = js_platform_options_t {
-            version: 1,
-            expose_garbage_collection: false,
-            trace_garbage_collection: false,
-            disable_optimizing_compiler: false,
-            trace_optimizations: false,
-            trace_deoptimizations: false,
-            enable_sampling_profiler: false,
-            sampling_profiler_interval: 0,
-            optimize_for_memory: true,
-        };
-        
-        if js_create_platform(uv_loop, &mut platform_options, &mut platform) != 0 {
-            uv_loop_delete(uv_loop);
-            return Err(BareError::RuntimeError("Failed to create JS platform".into()));
-        }
-
+        let owned = Runtime::new()?;
         *runtime = Some(GlobalRuntime {
-            uv_loop,
-            platform,
+            uv_loop: owned.uv_loop,
+            platform: owned.platform,
         });
+        // The global keeps the loop and platform alive for the rest of the process
+        std::mem::forget(owned);
     }
     Ok(())
 }
 
+/// Get the process-wide runtime created by `init_runtime_once`.
+///
+/// The returned `GlobalRuntime` does not own anything: it copies the raw
+/// pointers of the singleton, which are never released.
+/// A panic in another thread while it held the singleton's lock doesn't make
+/// this fail: the lock is recovered rather than left poisoned.
 pub unsafe fn get_runtime() -> BareResult<GlobalRuntime> {
-    let runtime = RUNTIME.lock().unwrap();
+    let runtime = lock_runtime();
     runtime.as_ref()
         .map(|r| GlobalRuntime { 
             uv_loop: r.uv_loop, 
@@ -1,124 +1,503 @@
-use bare_rs::{BareResult, BareError, init_runtime_once, get_runtime, set_stack_size, handle_js_exception};
-use bare_rs::bindings::*;
-use std::ffi::CString;
-use std::ptr;
-use log::{info, debug, error};
+use bare_rs::{trace_ffi, Bare, BareResult, BareError, BareOptions, PlatformOptions, Runtime, Value, run_with_stack_size, DEFAULT_STACK_SIZE};
+use log::{info, error};
 use env_logger::Env;
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::process;
 
-fn main() -> BareResult<()> {
+/// Exit status for a script that threw or didn't compile, as with the bare CLI
+const EXIT_SCRIPT_ERROR: i32 = 1;
+
+/// Exit status for errors raised by bare-rs rather than the script (EX_SOFTWARE)
+const EXIT_RUST_ERROR: i32 = 70;
+
+/// Where `--inspect` listens unless given an address, as in Node
+const DEFAULT_INSPECT_ADDR: &str = "127.0.0.1:9229";
+
+const USAGE: &str = "Usage: bare-rs [--repl] [--memory-limit <size>] [--stack-size <size>] [--inspect[=[host:]port]] [--inspect-brk[=[host:]port]] [--trace-gc] [--trace-opt] [--trace-deopt] [--check] [--encoding <utf-8|utf-16le|latin1>] [--filename <path>] [<script_path> | - | -e <code>] [--] [args...]";
+
+/// Full `--help` text: the usage line, then every option
+const HELP: &str = "\
+Run a JavaScript file, stdin or inline code on bare.
+
+Options:
+  -e, --eval <code>            run <code> instead of a file
+  -                            read the script from stdin
+  --repl                       start a REPL, the default on a terminal without a script
+  -c, --check                  report syntax errors without running the script
+  --encoding <name>            decode the script from utf-8, utf-16le or latin1
+  --filename <path>            where stdin and -e scripts live, for __filename,
+                               __dirname and relative requires
+  --memory-limit <size>        cap the JS heap, e.g. 512m
+  --stack-size <size>          stack size of the runtime thread (64m by default)
+  --inspect[=[host:]port]      let Chrome DevTools attach (127.0.0.1:9229 by default)
+  --inspect-brk[=[host:]port]  like --inspect, and break on the first line
+  --trace-gc                   print V8 GC traces
+  --trace-opt                  print V8 optimizer traces
+  --trace-deopt                print V8 deoptimization traces
+  -h, --help                   print this help
+  -v, --version                print the versions of bare-rs, bare and V8
+
+Arguments after the script path or `--` are passed to the script in Bare.argv.";
+
+/// What the command line asks for
+enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+/// Where the script to run comes from
+enum Script {
+    File(String),
+    Stdin,
+    Eval(String),
+    // No script was given and stdin is a terminal
+    Repl,
+}
+
+impl Script {
+    /// Name the script is loaded under: its path, or for scripts without a
+    /// file the `--filename` given, if any, or a placeholder
+    fn filename<'a>(&'a self, logical: Option<&'a str>) -> &'a str {
+        match self {
+            Script::File(path) => path,
+            Script::Stdin => logical.unwrap_or("[stdin]"),
+            Script::Eval(_) => logical.unwrap_or("[eval]"),
+            Script::Repl => "[repl]",
+        }
+    }
+}
+
+/// Text encoding of a script file or stdin
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Latin1,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> BareResult<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(BareError::RuntimeError(format!(
+                "Unsupported encoding '{}': expected utf-8, utf-16le or latin1", name
+            ))),
+        }
+    }
+
+    /// Decode `bytes` into a UTF-8 string the JS engine can load
+    fn decode(self, bytes: &[u8]) -> BareResult<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|e| BareError::RuntimeError(format!("Script is not valid UTF-8: {}", e))),
+            Encoding::Utf16Le => {
+                if bytes.len() % 2 != 0 {
+                    return Err(BareError::RuntimeError("Script is not valid UTF-16LE: odd number of bytes".into()));
+                }
+                let units: Vec<u16> = bytes.chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                // Drop the byte order mark, if any
+                let units = units.strip_prefix(&[0xFEFF]).unwrap_or(&units);
+                String::from_utf16(units)
+                    .map_err(|e| BareError::RuntimeError(format!("Script is not valid UTF-16LE: {}", e)))
+            }
+            // Latin-1 bytes are the first 256 Unicode code points
+            Encoding::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+        }
+    }
+}
+
+/// Parsed command line
+struct Args {
+    script: Script,
+    // Forwarded to the script, which sees them in `Bare.argv`
+    script_args: Vec<String>,
+    memory_limit: Option<usize>,
+    stack_size: Option<usize>,
+    encoding: Encoding,
+    // Logical path of stdin and -e scripts, resolved against the working
+    // directory
+    filename: Option<String>,
+    // Serve the inspector here, and with `inspect_brk` wait for a debugger
+    inspect: Option<SocketAddr>,
+    inspect_brk: bool,
+    // Only compile the script and report syntax errors
+    check: bool,
+    // V8 tracing, printed by the engine to stdout
+    trace_gc: bool,
+    trace_opt: bool,
+    trace_deopt: bool,
+}
+
+/// Parse a size such as `1048576`, `512m` or `2g` into a byte count, naming
+/// the option as `what` in errors
+fn parse_size(what: &str, size: &str) -> BareResult<usize> {
+    let invalid = || BareError::RuntimeError(format!(
+        "Invalid {} '{}': expected bytes or a number with a k, m or g suffix", what, size
+    ));
+
+    let lower = size.to_ascii_lowercase();
+    let (digits, multiplier) = match lower.as_bytes().last() {
+        Some(b'k') => (&lower[..lower.len() - 1], 1024),
+        Some(b'm') => (&lower[..lower.len() - 1], 1024 * 1024),
+        Some(b'g') => (&lower[..lower.len() - 1], 1024 * 1024 * 1024),
+        _ => (lower.as_str(), 1),
+    };
+
+    let bytes = digits.parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(invalid)?;
+    if bytes == 0 {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// Parse the address of `--inspect`, where a bare port listens on localhost
+fn parse_inspect_addr(addr: &str) -> BareResult<SocketAddr> {
+    let addr = if !addr.is_empty() && addr.bytes().all(|b| b.is_ascii_digit()) {
+        format!("127.0.0.1:{}", addr)
+    } else {
+        addr.to_string()
+    };
+
+    addr.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| BareError::RuntimeError(format!("Invalid inspector address '{}'. {}", addr, USAGE)))
+}
+
+/// Parse the command line into the script to run and its options
+fn parse_args(args: &[String]) -> BareResult<Command> {
+    let mut eval = None;
+    let mut file = None;
+    let mut script_args = Vec::new();
+    let mut memory_limit = None;
+    let mut stack_size = None;
+    let mut encoding = Encoding::Utf8;
+    let mut filename = None;
+    let mut trace_gc = false;
+    let mut trace_opt = false;
+    let mut trace_deopt = false;
+    let mut inspect = None;
+    let mut inspect_brk = false;
+    let mut check = false;
+    let mut repl = false;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--eval" => {
+                let code = args.next()
+                    .ok_or_else(|| BareError::RuntimeError(format!("{} requires an argument. {}", arg, USAGE)))?;
+                eval = Some(code.clone());
+            }
+            "--memory-limit" => {
+                let size = args.next()
+                    .ok_or_else(|| BareError::RuntimeError(format!("{} requires an argument. {}", arg, USAGE)))?;
+                memory_limit = Some(parse_size("memory limit", size)?);
+            }
+            "--stack-size" => {
+                let size = args.next()
+                    .ok_or_else(|| BareError::RuntimeError(format!("{} requires an argument. {}", arg, USAGE)))?;
+                stack_size = Some(parse_size("stack size", size)?);
+            }
+            "--encoding" => {
+                let name = args.next()
+                    .ok_or_else(|| BareError::RuntimeError(format!("{} requires an argument. {}", arg, USAGE)))?;
+                encoding = Encoding::parse(name)?;
+            }
+            "-h" | "--help" => return Ok(Command::Help),
+            "-v" | "--version" => return Ok(Command::Version),
+            "--repl" => repl = true,
+            "-c" | "--check" => check = true,
+            "--filename" => {
+                let path = args.next()
+                    .ok_or_else(|| BareError::RuntimeError(format!("{} requires an argument. {}", arg, USAGE)))?;
+                filename = Some(path.clone());
+            }
+            "--trace-gc" => trace_gc = true,
+            "--trace-opt" => trace_opt = true,
+            "--trace-deopt" => trace_deopt = true,
+            _ if arg == "--inspect" || arg == "--inspect-brk"
+                || arg.starts_with("--inspect=") || arg.starts_with("--inspect-brk=") =>
+            {
+                let (flag, addr) = arg.split_once('=').unwrap_or((arg, DEFAULT_INSPECT_ADDR));
+                inspect = Some(parse_inspect_addr(addr)?);
+                inspect_brk = flag == "--inspect-brk";
+            }
+            // Everything after `--` or the script path belongs to the script
+            "--" => {
+                script_args.extend(args.by_ref().cloned());
+            }
+            _ if arg.starts_with('-') && arg != "-" && file.is_none() => {
+                return Err(BareError::RuntimeError(format!("Unknown option '{}'. {}", arg, USAGE)));
+            }
+            _ if file.is_none() => {
+                file = Some(arg.clone());
+                script_args.extend(args.by_ref().cloned());
+            }
+            _ => {}
+        }
+    }
+
+    let script = match (eval, file) {
+        (Some(_), _) | (_, Some(_)) if repl => Err(BareError::RuntimeError(
+            format!("Cannot start a REPL and run a script at the same time. {}", USAGE)
+        )),
+        (None, None) if repl => Ok(Script::Repl),
+        (Some(_), Some(_)) => Err(BareError::RuntimeError(
+            format!("Cannot evaluate -e/--eval code and run a script file at the same time. {}", USAGE)
+        )),
+        (Some(code), None) => Ok(Script::Eval(code)),
+        (None, Some(path)) if path == "-" => Ok(Script::Stdin),
+        (None, Some(path)) => Ok(Script::File(path)),
+        // Without a script, be interactive on a terminal and otherwise run
+        // what is piped in, like node. Arguments with nothing to pass them
+        // to are a mistake.
+        (None, None) if !io::stdin().is_terminal() => Ok(Script::Stdin),
+        (None, None) if script_args.is_empty() => Ok(Script::Repl),
+        (None, None) => Err(BareError::RuntimeError(format!("No script file provided. {}", USAGE))),
+    }?;
+
+    if filename.is_some() && matches!(script, Script::File(_) | Script::Repl) {
+        return Err(BareError::RuntimeError(format!("--filename only applies to stdin and -e scripts. {}", USAGE)));
+    }
+
+    Ok(Command::Run(Args {
+        script,
+        script_args,
+        memory_limit,
+        stack_size,
+        encoding,
+        filename,
+        inspect,
+        inspect_brk,
+        check,
+        trace_gc,
+        trace_opt,
+        trace_deopt,
+    }))
+}
+
+fn main() {
     // Initialize logger with INFO level by default, can be overridden with RUST_LOG env var
     env_logger::Builder::from_env(Env::default().default_filter_or("error"))
         .init();
-    
+
+    // Run on a thread with a larger stack than the main thread's, so scripts
+    // have room for deep recursion
+    let args: Vec<String> = env::args().collect();
+    let result = parse_args(&args).and_then(|command| match command {
+        Command::Run(args) => {
+            let stack_size = args.stack_size.unwrap_or(DEFAULT_STACK_SIZE);
+            run_with_stack_size(stack_size, move || run(args))
+        }
+        Command::Help => {
+            println!("{}\n\n{}", USAGE, HELP);
+            Ok(0)
+        }
+        Command::Version => print_version().map(|()| 0),
+    });
+
+    // Exit with the code chosen by the script, EXIT_SCRIPT_ERROR if it
+    // threw, or EXIT_RUST_ERROR if bare-rs itself failed, so the two kinds of
+    // failure are distinguishable from a shell
+    match result {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(match e {
+                BareError::JSError { .. } | BareError::CompileError { .. } => EXIT_SCRIPT_ERROR,
+                _ => EXIT_RUST_ERROR,
+            });
+        }
+    }
+}
+
+/// Print the versions of bare-rs and of the bare and JS engine it links
+fn print_version() -> BareResult<()> {
+    let runtime = Runtime::new()?;
+    println!("bare-rs {}", env!("CARGO_PKG_VERSION"));
+    println!("bare {}", Bare::version()?);
+    println!("{}", runtime.version()?);
+    Ok(())
+}
+
+fn run(args: Args) -> BareResult<i32> {
     info!("Starting Bare-rs...");
-    
-    // Set larger stack size
-    debug!("Setting stack size...");
-    set_stack_size()?;
-    debug!("Stack size set successfully");
-    
-    unsafe {
-        // Initialize global runtime
-        debug!("Initializing runtime...");
-        init_runtime_once()?;
-        let runtime = get_runtime()?;
-        debug!("Runtime initialized successfully");
-
-        // Initialize bare options with sane defaults
-        debug!("Initializing Bare options...");
-        let options = bare_options_t {
-            version: 0, // Current version
-            memory_limit: 1024 * 1024 * 1024, // 1GB memory limit
-        };
-        debug!("Bare options initialized with version {} and memory_limit {} MB", 
-            options.version, options.memory_limit / (1024 * 1024));
-
-        // Setup bare runtime with defaults
-        debug!("Setting up Bare runtime...");
-        let mut bare = ptr::null_mut();
-        let mut env = ptr::null_mut();
-        
-        // Default empty args
-        let args = vec![CString::new("bare-rs").unwrap()];
-        let mut c_args: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
-        
-        debug!("Calling bare_setup...");
-        let setup_result = bare_setup(
-            runtime.uv_loop,
-            runtime.platform,
-            &mut env,
-            c_args.len() as i32,
-            c_args.as_mut_ptr(),
-            &options,
-            &mut bare,
-        );
-        debug!("bare_setup returned: {}", setup_result);
-        
-        if setup_result != 0 {
-            return Err(BareError::SetupError("Failed to setup Bare runtime".into()));
-        }
-        debug!("Bare runtime setup successfully");
-
-        // Get command line args
-        let args: Vec<String> = env::args().collect();
-        
-        if args.len() <= 1 {
-            return Err(BareError::RuntimeError("No script file provided. Usage: bare-rs <script_path>".into()));
-        }
-
-        // Load script from file
-        debug!("Loading script from file: {}", args[1]);
-        let file_script = std::fs::read_to_string(&args[1])
-            .map_err(|e| BareError::RuntimeError(format!("Failed to read script file: {}", e)))?;
-        let script = CString::new(file_script)?;
-        let filename = CString::new(args[1].clone())?;
-
-        let source = uv_buf_t {
-            base: script.as_ptr() as *mut i8,
-            len: script.as_bytes().len(),
-        };
-
-        debug!("Loading script...");
-        let mut result = ptr::null_mut();
-        let load_result = bare_load(bare, filename.as_ptr(), &source, &mut result);
-        debug!("bare_load returned: {}", load_result);
-        
-        if load_result != 0 {
-            return Err(BareError::RuntimeError("Failed to load script".into()));
-        }
-        debug!("Script loaded successfully");
-
-        debug!("Running script...");
-        let run_result = bare_run(bare);
-        debug!("bare_run() result: {}", run_result);
-        
-        // Check for exceptions
-        if let Err(e) = handle_js_exception(env) {
-            error!("JavaScript error: {}", e);
-            
-            // Cleanup before returning error
-            let mut exit_code = 1;
-            let _ = bare_teardown(bare, &mut exit_code);
-            debug!("Teardown after error completed with exit code {}", exit_code);
-            
-            return Err(e);
-        }
-
-        // Cleanup
-        debug!("Starting cleanup...");
-        let mut exit_code = 0;
-        
-        debug!("Tearing down Bare runtime...");
-        let teardown_result = bare_teardown(bare, &mut exit_code);
-        debug!("bare_teardown returned: {} with exit_code: {}", teardown_result, exit_code);
-        
-        if teardown_result != 0 {
-            return Err(BareError::RuntimeError("Failed to teardown Bare runtime".into()));
-        }
-        debug!("Bare runtime torn down successfully");
-
-        info!("Bare-rs completed successfully");
-        Ok(())
+
+    let Args { script, script_args, memory_limit, encoding, inspect, inspect_brk, check, filename, trace_gc, trace_opt, trace_deopt, .. } = args;
+
+    // Initialize runtime
+    trace_ffi!("Initializing runtime...");
+    // The platform is configured once, so the tracing flags must be known here
+    #[cfg(not(feature = "inspector"))]
+    if let Some(addr) = inspect {
+        return Err(BareError::RuntimeError(format!(
+            "Cannot inspect on {}: bare-rs was built without the `inspector` feature", addr
+        )));
+    }
+    let platform_options = PlatformOptions::default()
+        .abort_diagnostic(true)
+        .trace_gc(trace_gc)
+        .trace_optimizations(trace_opt)
+        .trace_deoptimizations(trace_deopt);
+    #[cfg(feature = "inspector")]
+    let platform_options = match inspect {
+        Some(addr) => platform_options.inspector(addr),
+        None => platform_options,
+    };
+    let runtime = Runtime::with_platform_options(platform_options)?;
+    trace_ffi!("Runtime initialized successfully");
+
+    // Initialize bare options with sane defaults, overriding the memory
+    // limit if one was given
+    trace_ffi!("Initializing Bare options...");
+    let mut options = BareOptions::default();
+    if let Some(bytes) = memory_limit {
+        options = options.memory_limit(bytes);
+    }
+    trace_ffi!("Bare options initialized with version {} and memory_limit {} MB",
+        options.build().version, options.build().memory_limit / (1024 * 1024));
+
+    // Setup bare runtime with defaults
+    trace_ffi!("Setting up Bare runtime...");
+    // The script sees `Bare.argv` as the program name, the script path (for
+    // file and stdin scripts), then its own arguments, like the bare CLI
+    let mut bare_args = vec!["bare-rs"];
+    match &script {
+        Script::File(path) => bare_args.push(path),
+        Script::Stdin => bare_args.push("-"),
+        Script::Eval(_) | Script::Repl => {}
+    }
+    bare_args.extend(script_args.iter().map(String::as_str));
+    let bare = runtime.setup(&bare_args, options)?;
+    trace_ffi!("Bare runtime setup successfully");
+
+    if check {
+        trace_ffi!("Checking script syntax...");
+        check_syntax(&bare, script, filename.as_deref(), encoding)?;
+        return bare.teardown();
+    }
+
+    if let Some(url) = bare.inspector_url() {
+        eprintln!("Debugger listening on {}", url);
+        eprintln!("For help, see: https://nodejs.org/en/docs/inspector");
+    }
+    if inspect_brk {
+        bare.wait_for_debugger(script.filename(filename.as_deref()))?;
+    }
+
+    // Run the script from a file through bare's module loader, so relative
+    // requires resolve next to it, or from stdin or the -e/--eval argument,
+    // which resolve from their --filename or the working directory.
+    // Sources in other encodings are decoded here first, since bare only
+    // reads UTF-8.
+    trace_ffi!("Running script...");
+    let name = script.filename(filename.as_deref()).to_string();
+    let result = match script {
+        Script::File(path) if encoding == Encoding::Utf8 => {
+            trace_ffi!("Loading module from file: {}", path);
+            bare.load_module(&path)
+        }
+        Script::File(path) => {
+            trace_ffi!("Loading and decoding script from file: {}", path);
+            let bytes = fs::read(&path)?;
+            // Resolve the path as load_module would, for relative requires
+            let filename = fs::canonicalize(&path)?;
+            bare.eval_with_filename(&encoding.decode(&bytes)?, &filename.to_string_lossy())
+        }
+        Script::Stdin => {
+            trace_ffi!("Loading script from stdin");
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            bare.eval_with_filename(&encoding.decode(&bytes)?, &name)
+        }
+        Script::Eval(code) => {
+            trace_ffi!("Evaluating script from command line");
+            bare.eval_with_filename(&code, &name)
+        }
+        Script::Repl => repl(&bare).and_then(|()| Value::new_undefined(&bare)),
+    };
+
+    if let Err(e) = result {
+        // `main` reports the error, so it isn't logged here as well. Give the
+        // script's exit handlers a chance to run; the instance is then torn
+        // down when `bare` is dropped
+        if let Err(e) = bare.shutdown(1) {
+            error!("Failed to shut down gracefully: {}", e);
+        }
+        return Err(e);
+    }
+    trace_ffi!("Script ran successfully");
+
+    // Cleanup
+    trace_ffi!("Tearing down Bare runtime...");
+    let exit_code = bare.teardown()?;
+    trace_ffi!("Bare runtime torn down successfully with exit_code: {}", exit_code);
+
+    info!("Bare-rs completed successfully");
+    Ok(exit_code)
+}
+
+/// Compile `script` without running it, for `--check`
+fn check_syntax(bare: &Bare, script: Script, logical: Option<&str>, encoding: Encoding) -> BareResult<()> {
+    let source = match &script {
+        Script::File(path) => encoding.decode(&fs::read(path)?)?,
+        Script::Stdin => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            encoding.decode(&bytes)?
+        }
+        Script::Eval(code) => code.clone(),
+        Script::Repl => return Err(BareError::RuntimeError(format!("--check requires a script. {}", USAGE))),
+    };
+    bare.check_syntax(&source, script.filename(logical))
+}
+
+/// Read-eval-print loop for an interactive terminal, until end of input or
+/// `.exit`. Lines are evaluated as global code through indirect `eval`, so
+/// `var` and function declarations carry over between them.
+fn repl(bare: &Bare) -> BareResult<()> {
+    let eval = bare.get_global("eval")?;
+    let mut lines = io::stdin().lock();
+    let mut line = String::new();
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        line.clear();
+        if lines.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        match line.trim() {
+            "" => continue,
+            ".exit" => return Ok(()),
+            _ => {}
+        }
+
+        let result = Value::new_string(bare, &line)
+            .and_then(|code| eval.call(&[code]))
+            .and_then(|value| value.to_debug_string());
+        match result {
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("Uncaught {}", e),
+        }
+
+        // Give callbacks the line scheduled a chance to run
+        if let Err(e) = bare.run_nowait() {
+            eprintln!("Uncaught {}", e);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::bindings::js_env_t;
+
+/// Counters of a Bare instance since it was set up, as reported by
+/// `Bare::metrics`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeMetrics {
+    /// Sources loaded through `load`, `eval*`, `load_module` and friends,
+    /// whether or not they compiled
+    pub scripts_loaded: u64,
+    /// Times the event loop was run to completion through `run`
+    pub scripts_run: u64,
+    /// JS exceptions reported to Rust, by error type such as `"TypeError"`
+    pub exceptions: BTreeMap<String, u64>,
+    /// Time spent loading scripts and running the event loop
+    pub run_time: Duration,
+}
+
+thread_local! {
+    // Metrics of the instances on this thread by JS environment, so that
+    // exceptions are counted wherever they are taken from the environment,
+    // not only by the `Bare` methods that know their instance
+    static REGISTRY: RefCell<HashMap<usize, Rc<RefCell<RuntimeMetrics>>>> = RefCell::new(HashMap::new());
+}
+
+/// Count exceptions taken from `env` in `metrics` until `unregister`
+pub(crate) fn register(env: *mut js_env_t, metrics: &Rc<RefCell<RuntimeMetrics>>) {
+    REGISTRY.with(|registry| {
+        registry.borrow_mut().insert(env as usize, metrics.clone());
+    });
+}
+
+pub(crate) fn unregister(env: *mut js_env_t) {
+    REGISTRY.with(|registry| {
+        registry.borrow_mut().remove(&(env as usize));
+    });
+}
+
+/// Count an exception of type `kind` taken from `env`, if `env` belongs to
+/// a Bare instance on this thread
+pub(crate) fn record_exception(env: *mut js_env_t, kind: &str) {
+    let metrics = REGISTRY.with(|registry| registry.borrow().get(&(env as usize)).cloned());
+    if let Some(metrics) = metrics {
+        *metrics.borrow_mut().exceptions.entry(kind.to_string()).or_insert(0) += 1;
+    }
+}
+
+impl RuntimeMetrics {
+    /// Render the counters in the Prometheus text exposition format, with
+    /// metric names prefixed by `bare_rs_`
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE bare_rs_scripts_loaded_total counter");
+        let _ = writeln!(out, "bare_rs_scripts_loaded_total {}", self.scripts_loaded);
+        let _ = writeln!(out, "# TYPE bare_rs_scripts_run_total counter");
+        let _ = writeln!(out, "bare_rs_scripts_run_total {}", self.scripts_run);
+        let _ = writeln!(out, "# TYPE bare_rs_exceptions_total counter");
+        for (kind, count) in &self.exceptions {
+            let kind = kind.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            let _ = writeln!(out, "bare_rs_exceptions_total{{kind=\"{}\"}} {}", kind, count);
+        }
+        let _ = writeln!(out, "# TYPE bare_rs_run_seconds_total counter");
+        let _ = writeln!(out, "bare_rs_run_seconds_total {}", self.run_time.as_secs_f64());
+
+        out
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Bare, BareError, BareResult, Value};
+
+// bare's C API can only register native addons, so virtual modules are
+// provided by hooking bare-module from JS: `Module.resolve` maps a registered
+// name to a `bare-rs:` URL, and `Module.load` supplies the JSON source for it.
+// The hook is installed once per instance and kept on a global symbol; loading
+// this source again just returns the existing register function.
+const JSON_MODULES: &str = r#"
+const key = Symbol.for('bare-rs.jsonModules')
+
+if (globalThis[key] === undefined) {
+  const Module = require('module')
+  const { resolve, load } = Module
+  const urls = new Map()
+  const sources = new Map()
+
+  Module.resolve = function (specifier, ...args) {
+    return urls.get(specifier) || resolve.call(this, specifier, ...args)
+  }
+
+  Module.load = function (url, source = null, opts = {}) {
+    if (!ArrayBuffer.isView(source) && typeof source !== 'string' && source !== null) {
+      opts = source
+      source = null
+    }
+    const json = sources.get(url.href)
+    return load.call(this, url, json === undefined ? source : json, opts)
+  }
+
+  Object.defineProperty(globalThis, key, {
+    value: (name, json) => {
+      const url = new URL(`bare-rs:/${encodeURIComponent(name)}.json`)
+      urls.set(name, url)
+      sources.set(url.href, json)
+    }
+  })
+}
+
+module.exports = globalThis[key]
+"#;
+
+// Same hook pattern for `Bare::set_module_resolver`: `Module.resolve` asks the
+// resolver first and maps any specifier it answers to a `bare-rs:` URL, and
+// `Module.load` supplies the returned source for that URL. Modules are cached
+// by URL, so a specifier is only loaded once whichever script requires it.
+const MODULE_RESOLVER: &str = r#"
+const key = Symbol.for('bare-rs.moduleResolver')
+
+if (globalThis[key] === undefined) {
+  const Module = require('module')
+  const { resolve, load } = Module
+  const sources = new Map()
+  let resolver = null
+
+  Module.resolve = function (specifier, parentURL, ...args) {
+    if (resolver !== null) {
+      const referrer = parentURL ? String(parentURL.href || parentURL) : ''
+      const source = resolver(specifier, referrer)
+      if (typeof source === 'string') {
+        const url = new URL(`bare-rs:/${encodeURIComponent(specifier)}.js`)
+        if (!sources.has(url.href)) sources.set(url.href, source)
+        return url
+      }
+    }
+    return resolve.call(this, specifier, parentURL, ...args)
+  }
+
+  Module.load = function (url, source = null, opts = {}) {
+    if (!ArrayBuffer.isView(source) && typeof source !== 'string' && source !== null) {
+      opts = source
+      source = null
+    }
+    const js = sources.get(url.href)
+    return load.call(this, url, js === undefined ? source : js, opts)
+  }
+
+  Object.defineProperty(globalThis, key, {
+    value: (fn) => { resolver = fn }
+  })
+}
+
+module.exports = globalThis[key]
+"#;
+
+/// Register `value` as a module named `name`, so scripts loaded afterwards get
+/// it from `require(name)`
+pub(crate) fn import_json(bare: &Bare, name: &str, value: &serde_json::Value) -> BareResult<()> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| BareError::RuntimeError(format!("Failed to serialize module {}: {}", name, e)))?;
+
+    let register = bare.load(JSON_MODULES, "[bare-rs]")?;
+    register.call(&[Value::new_string(bare, name)?, Value::new_string(bare, &json)?])?;
+    Ok(())
+}
+
+/// Make `resolver` the function that bare-module consults before resolving a
+/// specifier, replacing any previous one
+pub(crate) fn set_resolver(bare: &Bare, resolver: Value) -> BareResult<()> {
+    let install = bare.load(MODULE_RESOLVER, "[bare-rs]")?;
+    install.call(&[resolver])?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::cell::{Cell, RefCell};
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use serde_json::json;
+
+use crate::bindings::*;
+use crate::function::panic_message;
+use crate::{BareError, BareResult, Value};
+
+/// CPU profiler for a single JS environment.
+///
+/// libjs has no API for reading the sampling profiler's output, so this
+/// drives V8's `Profiler` domain through an inspector session instead, which
+/// yields profiles in the `.cpuprofile` format understood by Chrome DevTools.
+pub(crate) struct Profiler {
+    env: *mut js_env_t,
+    inspector: *mut js_inspector_t,
+    // Boxed so the address handed to the response callback stays stable
+    responses: Box<RefCell<Vec<String>>>,
+    next_id: Cell<u64>,
+}
+
+impl Profiler {
+    /// Connect an inspector session to `env` and start sampling every
+    /// `interval_us` microseconds
+    pub(crate) unsafe fn start(env: *mut js_env_t, interval_us: i32) -> BareResult<Profiler> {
+        let mut inspector = ptr::null_mut();
+        if js_create_inspector(env, &mut inspector) != 0 {
+            return Err(BareError::RuntimeError("Failed to create inspector".into()));
+        }
+
+        let profiler = Profiler {
+            env,
+            inspector,
+            responses: Box::new(RefCell::new(Vec::new())),
+            next_id: Cell::new(1),
+        };
+
+        let responses = &*profiler.responses as *const RefCell<Vec<String>> as *mut c_void;
+        if js_on_inspector_response(env, inspector, Some(on_response), responses) != 0
+            || js_connect_inspector(env, inspector) != 0
+        {
+            return Err(BareError::RuntimeError("Failed to connect inspector".into()));
+        }
+
+        profiler.send("Profiler.enable", json!({}))?;
+        profiler.send("Profiler.setSamplingInterval", json!({ "interval": interval_us }))?;
+        profiler.send("Profiler.start", json!({}))?;
+        Ok(profiler)
+    }
+
+    /// Stop sampling and return the profile collected since the last call as
+    /// `.cpuprofile` JSON, then start sampling again
+    pub(crate) fn take_profile(&self) -> BareResult<String> {
+        let mut result = self.send("Profiler.stop", json!({}))?;
+        self.send("Profiler.start", json!({}))?;
+
+        let profile = result.get_mut("profile")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| BareError::RuntimeError("Profiler.stop returned no profile".into()))?;
+        serde_json::to_string(&profile)
+            .map_err(|e| BareError::RuntimeError(format!("Failed to serialize profile: {}", e)))
+    }
+
+    // Send a protocol request and return its result. V8 answers requests
+    // synchronously, so the response has arrived by the time this returns.
+    fn send(&self, method: &str, params: serde_json::Value) -> BareResult<serde_json::Value> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+
+        let request = json!({ "id": id, "method": method, "params": params }).to_string();
+        unsafe {
+            let mut message = ptr::null_mut();
+            if js_create_string_utf8(self.env, request.as_ptr(), request.len(), &mut message) != 0
+                || js_send_inspector_request(self.env, self.inspector, message) != 0
+            {
+                return Err(BareError::RuntimeError(format!("Failed to send {}", method)));
+            }
+        }
+
+        // Events such as console messages may arrive too; keep only ours
+        let mut responses = self.responses.borrow_mut();
+        let response = responses.drain(..)
+            .filter_map(|response| serde_json::from_str::<serde_json::Value>(&response).ok())
+            .find(|response| response.get("id").and_then(serde_json::Value::as_u64) == Some(id))
+            .ok_or_else(|| BareError::RuntimeError(format!("No response to {}", method)))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(BareError::RuntimeError(format!("{} failed: {}", method, error)));
+        }
+        Ok(response.get("result").cloned().unwrap_or(serde_json::Value::Null))
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        if unsafe { js_destroy_inspector(self.env, self.inspector) } != 0 {
+            log::error!("Failed to destroy inspector");
+        }
+    }
+}
+
+unsafe extern "C" fn on_response(
+    env: *mut js_env_t,
+    _inspector: *mut js_inspector_t,
+    message: *mut js_value_t,
+    data: *mut c_void,
+) {
+    let responses = &*(data as *const RefCell<Vec<String>>);
+    // Unwinding into the inspector is undefined behavior
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match Value::from_raw(env, message).as_string() {
+        Ok(message) => responses.borrow_mut().push(message),
+        Err(e) => log::error!("Failed to read inspector response: {}", e),
+    }));
+    if let Err(payload) = result {
+        log::error!("Inspector response handler panicked: {}", panic_message(&*payload));
+    }
+}
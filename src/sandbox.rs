@@ -0,0 +1,125 @@
+use std::ffi::CString;
+use std::ptr;
+
+use crate::bindings::*;
+use crate::{Bare, BareError, BareResult, Value};
+
+/// Globals that `BareOptions::sandbox` keeps: the ECMAScript built-ins, plus
+/// console, timers and the encoding, URL and event helpers of bare. Every
+/// other string-keyed global is deleted.
+///
+/// `WebAssembly` and `SharedArrayBuffer` are left out, as is `gc` when the
+/// runtime exposes it.
+pub const SANDBOX_GLOBALS: &[&str] = &[
+    // ECMAScript
+    "globalThis", "undefined", "NaN", "Infinity",
+    "eval", "isFinite", "isNaN", "parseFloat", "parseInt",
+    "decodeURI", "decodeURIComponent", "encodeURI", "encodeURIComponent", "escape", "unescape",
+    "Object", "Function", "Boolean", "Symbol", "Number", "BigInt", "Math", "Date", "String", "RegExp",
+    "Error", "AggregateError", "EvalError", "RangeError", "ReferenceError", "SyntaxError", "TypeError", "URIError",
+    "Array", "Int8Array", "Uint8Array", "Uint8ClampedArray", "Int16Array", "Uint16Array", "Int32Array",
+    "Uint32Array", "Float32Array", "Float64Array", "BigInt64Array", "BigUint64Array",
+    "Map", "Set", "WeakMap", "WeakSet", "WeakRef", "FinalizationRegistry",
+    "ArrayBuffer", "DataView", "Atomics", "JSON", "Promise", "Proxy", "Reflect", "Intl", "Iterator",
+    // bare
+    "Bare", "global", "console", "queueMicrotask",
+    "setTimeout", "clearTimeout", "setInterval", "clearInterval", "setImmediate", "clearImmediate",
+    "TextEncoder", "TextDecoder", "URL", "URLSearchParams", "atob", "btoa", "structuredClone",
+    "Event", "EventTarget", "AbortController", "AbortSignal",
+];
+
+/// Members of the `Bare` namespace that `BareOptions::sandbox` deletes: the
+/// ones that end or suspend the process, load native addons, start threads
+/// or reveal the command line.
+pub const SANDBOX_REMOVED_BARE_MEMBERS: &[&str] = &[
+    "exit", "suspend", "resume", "wakeup", "idle", "restart", "Addon", "Thread", "argv",
+];
+
+/// What the sandbox took from scripts but bare-rs still needs: `Bare.exit`,
+/// for `Bare::shutdown`
+pub(crate) struct Sandbox {
+    env: *mut js_env_t,
+    exit: *mut js_ref_t,
+}
+
+impl Sandbox {
+    /// Call the original `Bare.exit` with `code`
+    pub(crate) fn exit(&self, process: Value, code: Value) -> BareResult<()> {
+        let exit = unsafe {
+            let mut exit = ptr::null_mut();
+            if js_get_reference_value(self.env, self.exit, &mut exit) != 0 {
+                return Err(BareError::RuntimeError("Failed to get Bare.exit".into()));
+            }
+            Value::from_raw(self.env, exit)
+        };
+        exit.call_with_this(process, &[code])?;
+        Ok(())
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if unsafe { js_delete_reference(self.env, self.exit) } != 0 {
+            log::error!("Failed to delete Bare.exit reference");
+        }
+    }
+}
+
+/// Strip the globals of `bare` down to `SANDBOX_GLOBALS` and remove
+/// `SANDBOX_REMOVED_BARE_MEMBERS` from `Bare`. A global that can't be
+/// deleted fails the whole setup rather than being left in place.
+pub(crate) fn apply(bare: &Bare) -> BareResult<Sandbox> {
+    let env = bare.env_ptr();
+    let global = bare.get_global("globalThis")?;
+
+    // Built-ins aren't enumerable, so list them through Object
+    let object = bare.get_global("Object")?;
+    let names = object.get_property("getOwnPropertyNames")?.call_with_this(object, &[global])?;
+    for i in 0..names.length()? {
+        let name = names.get_index(i)?.as_string()?;
+        if !SANDBOX_GLOBALS.contains(&name.as_str()) {
+            delete_property(global, &name)?;
+        }
+    }
+
+    let process = bare.get_global("Bare")?;
+    let exit = process.get_property("exit")?;
+    let mut reference = ptr::null_mut();
+    if unsafe { js_create_reference(env, exit.as_ptr(), 1, &mut reference) } != 0 {
+        return Err(BareError::RuntimeError("Failed to create Bare.exit reference".into()));
+    }
+    let sandbox = Sandbox { env, exit: reference };
+
+    // Methods and getters may live on the prototypes of `Bare` rather than on
+    // the object itself, so strip the whole chain below Object.prototype
+    let get_prototype = object.get_property("getPrototypeOf")?;
+    let base = object.get_property("prototype")?;
+    let mut target = process;
+    while !target.is_null()? && !strict_equals(target, base)? {
+        for member in SANDBOX_REMOVED_BARE_MEMBERS {
+            delete_property(target, member)?;
+        }
+        target = get_prototype.call_with_this(object, &[target])?;
+    }
+    Ok(sandbox)
+}
+
+fn delete_property(object: Value, name: &str) -> BareResult<()> {
+    let c_name = CString::new(name)?;
+    let mut deleted = false;
+    let status = unsafe {
+        js_delete_named_property(object.env_ptr(), object.as_ptr(), c_name.as_ptr(), &mut deleted)
+    };
+    if status != 0 || !deleted {
+        return Err(BareError::SetupError(format!("Failed to remove '{}' for the sandbox", name)));
+    }
+    Ok(())
+}
+
+fn strict_equals(a: Value, b: Value) -> BareResult<bool> {
+    let mut equal = false;
+    if unsafe { js_strict_equals(a.env_ptr(), a.as_ptr(), b.as_ptr(), &mut equal) } != 0 {
+        return Err(BareError::RuntimeError("Failed to compare values".into()));
+    }
+    Ok(equal)
+}
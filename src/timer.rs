@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::bindings::*;
+use crate::function::panic_message;
+use crate::{BareError, BareResult};
+
+/// One-shot uv timers running Rust closures on an instance's loop.
+///
+/// Pending timers keep the loop alive like JS timers do. Those that haven't
+/// fired by teardown are closed by `close_all`, so no handle outlives the
+/// instance that created it.
+pub(crate) struct Timers {
+    // Addresses of the timers that haven't fired or been closed yet
+    pending: Rc<RefCell<HashSet<usize>>>,
+}
+
+// `handle` comes first so a `uv_timer_t` pointer is also a `Timer` pointer
+#[repr(C)]
+struct Timer {
+    handle: uv_timer_t,
+    callback: Option<Box<dyn FnOnce()>>,
+    pending: Rc<RefCell<HashSet<usize>>>,
+}
+
+impl Timers {
+    pub(crate) fn new() -> Self {
+        Timers { pending: Rc::new(RefCell::new(HashSet::new())) }
+    }
+
+    /// Run `callback` on `uv_loop` once `delay` has elapsed
+    pub(crate) unsafe fn set_timeout(
+        &self,
+        uv_loop: *mut uv_loop_t,
+        delay: Duration,
+        callback: Box<dyn FnOnce()>,
+    ) -> BareResult<()> {
+        let timer = Box::into_raw(Box::new(Timer {
+            handle: std::mem::zeroed(),
+            callback: Some(callback),
+            pending: self.pending.clone(),
+        }));
+
+        if uv_timer_init(uv_loop, &mut (*timer).handle) != 0 {
+            drop(Box::from_raw(timer));
+            return Err(BareError::RuntimeError("Failed to initialize timer".into()));
+        }
+
+        let timeout = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+        if uv_timer_start(&mut (*timer).handle, Some(on_timeout), timeout, 0) != 0 {
+            // Initialized handles can only be freed once closed
+            uv_close(timer as *mut uv_handle_t, Some(on_close));
+            return Err(BareError::RuntimeError("Failed to start timer".into()));
+        }
+
+        self.pending.borrow_mut().insert(timer as usize);
+        Ok(())
+    }
+
+    /// Cancel and close every timer that hasn't fired yet. The handles are
+    /// freed by their close callbacks on the next loop iteration.
+    pub(crate) fn close_all(&self) {
+        let pending: Vec<_> = self.pending.borrow_mut().drain().collect();
+        for timer in pending {
+            unsafe {
+                uv_timer_stop(timer as *mut uv_timer_t);
+                uv_close(timer as *mut uv_handle_t, Some(on_close));
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn on_timeout(handle: *mut uv_timer_t) {
+    let timer = &mut *(handle as *mut Timer);
+    timer.pending.borrow_mut().remove(&(handle as usize));
+
+    if let Some(callback) = timer.callback.take() {
+        // Unwinding into libuv is undefined behavior
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(callback)) {
+            log::error!("Timer callback panicked: {}", panic_message(&*payload));
+        }
+    }
+
+    uv_close(handle as *mut uv_handle_t, Some(on_close));
+}
+
+unsafe extern "C" fn on_close(handle: *mut uv_handle_t) {
+    drop(Box::from_raw(handle as *mut Timer));
+}
@@ -0,0 +1,600 @@
+use std::ffi::CString;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+
+use serde::de::DeserializeOwned;
+
+use crate::bindings::*;
+use crate::inspect;
+use crate::json;
+use crate::{try_handle_js_exception, Bare, BareError, BareResult, JsArg};
+
+// Largest integer a double holds exactly, i.e. `Number.MAX_SAFE_INTEGER`
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Type of a JS value, as reported by `typeof`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Undefined,
+    Null,
+    Boolean,
+    Number,
+    String,
+    Symbol,
+    Object,
+    Function,
+    External,
+    BigInt,
+}
+
+impl ValueType {
+    #[allow(non_upper_case_globals)]
+    fn from_raw(value_type: js_value_type_t) -> BareResult<ValueType> {
+        match value_type {
+            js_value_type_t_js_undefined => Ok(ValueType::Undefined),
+            js_value_type_t_js_null => Ok(ValueType::Null),
+            js_value_type_t_js_boolean => Ok(ValueType::Boolean),
+            js_value_type_t_js_number => Ok(ValueType::Number),
+            js_value_type_t_js_string => Ok(ValueType::String),
+            js_value_type_t_js_symbol => Ok(ValueType::Symbol),
+            js_value_type_t_js_object => Ok(ValueType::Object),
+            js_value_type_t_js_function => Ok(ValueType::Function),
+            js_value_type_t_js_external => Ok(ValueType::External),
+            js_value_type_t_js_bigint => Ok(ValueType::BigInt),
+            other => Err(BareError::RuntimeError(format!("Unknown value type: {}", other))),
+        }
+    }
+
+    /// Name of the type, as used in JS error messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueType::Undefined => "undefined",
+            ValueType::Null => "null",
+            ValueType::Boolean => "boolean",
+            ValueType::Number => "number",
+            ValueType::String => "string",
+            ValueType::Symbol => "symbol",
+            ValueType::Object => "object",
+            ValueType::Function => "function",
+            ValueType::External => "external",
+            ValueType::BigInt => "bigint",
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A JS value together with the environment it belongs to.
+///
+/// A `Value` is a handle into the JS heap and is only valid while the
+/// environment that produced it is alive, so it borrows the `Bare` instance
+/// it came from for `'bare`.
+#[derive(Clone, Copy)]
+pub struct Value<'bare> {
+    env: *mut js_env_t,
+    raw: *mut js_value_t,
+    bare: PhantomData<&'bare ()>,
+}
+
+impl<'bare> Value<'bare> {
+    /// Wrap a raw value belonging to `env`.
+    ///
+    /// # Safety
+    ///
+    /// The caller picks `'bare`, which must not outlive the instance that
+    /// owns `env` nor the handle scope `raw` was created in.
+    pub unsafe fn from_raw(env: *mut js_env_t, raw: *mut js_value_t) -> Value<'bare> {
+        Value { env, raw, bare: PhantomData }
+    }
+
+    /// Create a JS string in `bare`'s environment
+    pub fn new_string(bare: &'bare Bare, value: &str) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        if unsafe { js_create_string_utf8(bare.env_ptr(), value.as_ptr(), value.len(), &mut raw) } != 0 {
+            return Err(BareError::RuntimeError("Failed to create string".into()));
+        }
+        Ok(Value { env: bare.env_ptr(), raw, bare: PhantomData })
+    }
+
+    /// Create a JS number in `bare`'s environment
+    pub fn new_number(bare: &'bare Bare, value: f64) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        if unsafe { js_create_double(bare.env_ptr(), value, &mut raw) } != 0 {
+            return Err(BareError::RuntimeError("Failed to create number".into()));
+        }
+        Ok(Value { env: bare.env_ptr(), raw, bare: PhantomData })
+    }
+
+    /// Get the JS boolean `value` in `bare`'s environment
+    pub fn new_bool(bare: &'bare Bare, value: bool) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        if unsafe { js_get_boolean(bare.env_ptr(), value, &mut raw) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get boolean".into()));
+        }
+        Ok(Value { env: bare.env_ptr(), raw, bare: PhantomData })
+    }
+
+    /// Get `null` in `bare`'s environment
+    pub fn new_null(bare: &'bare Bare) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        if unsafe { js_get_null(bare.env_ptr(), &mut raw) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get null".into()));
+        }
+        Ok(Value { env: bare.env_ptr(), raw, bare: PhantomData })
+    }
+
+    /// Create a `Uint8Array` holding a copy of `bytes` in `bare`'s environment.
+    ///
+    /// The bytes are copied into a new `ArrayBuffer` owned by the JS heap, so
+    /// `bytes` can be dropped or changed right away and the array lives as
+    /// long as scripts reference it. An external buffer would save the copy
+    /// but tie the Rust allocation to the garbage collector.
+    pub fn from_bytes(bare: &'bare Bare, bytes: &[u8]) -> BareResult<Value<'bare>> {
+        let env = bare.env_ptr();
+        let mut data = ptr::null_mut();
+        let mut buffer = ptr::null_mut();
+        let mut raw = ptr::null_mut();
+
+        unsafe {
+            if js_create_arraybuffer(env, bytes.len(), &mut data, &mut buffer) != 0 {
+                return Err(BareError::RuntimeError("Failed to create ArrayBuffer".into()));
+            }
+            if !bytes.is_empty() {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+            }
+            if js_create_typedarray(env, js_typedarray_type_t_js_uint8array, bytes.len(), buffer, 0, &mut raw) != 0 {
+                return Err(BareError::RuntimeError("Failed to create Uint8Array".into()));
+            }
+        }
+        Ok(Value { env, raw, bare: PhantomData })
+    }
+
+    /// Get `undefined` in `bare`'s environment
+    pub fn new_undefined(bare: &'bare Bare) -> BareResult<Value<'bare>> {
+        Value::undefined(bare.env_ptr())
+    }
+
+    pub(crate) fn undefined(env: *mut js_env_t) -> BareResult<Value<'bare>> {
+        let mut raw = ptr::null_mut();
+        if unsafe { js_get_undefined(env, &mut raw) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get undefined".into()));
+        }
+        Ok(Value { env, raw, bare: PhantomData })
+    }
+
+    /// Raw pointer to the underlying `js_value_t`
+    pub fn as_ptr(&self) -> *mut js_value_t {
+        self.raw
+    }
+
+    /// Raw pointer to the JS environment this value belongs to
+    pub fn env_ptr(&self) -> *mut js_env_t {
+        self.env
+    }
+
+    /// Get the type of the value, as reported by `typeof`
+    pub fn type_of(&self) -> BareResult<ValueType> {
+        let mut value_type = 0;
+        if unsafe { js_typeof(self.env, self.raw, &mut value_type) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get value type".into()));
+        }
+        ValueType::from_raw(value_type)
+    }
+
+    pub fn is_undefined(&self) -> BareResult<bool> {
+        let mut result = false;
+        if unsafe { js_is_undefined(self.env, self.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError("Failed to check for undefined".into()));
+        }
+        Ok(result)
+    }
+
+    pub fn is_null(&self) -> BareResult<bool> {
+        let mut result = false;
+        if unsafe { js_is_null(self.env, self.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError("Failed to check for null".into()));
+        }
+        Ok(result)
+    }
+
+    /// Check `value instanceof name`, where `name` is a global constructor
+    /// such as `Error` or a class the script defined on `globalThis`
+    pub fn instance_of(&self, name: &str) -> BareResult<bool> {
+        let mut global = ptr::null_mut();
+        if unsafe { js_get_global(self.env, &mut global) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get global object".into()));
+        }
+
+        let constructor = Value { env: self.env, raw: global, bare: PhantomData }.get_property(name)?;
+        if constructor.type_of()? != ValueType::Function {
+            return Err(type_error(format!("'{}' is not a constructor", name)));
+        }
+
+        let mut result = false;
+        if unsafe { js_instanceof(self.env, self.raw, constructor.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to check instanceof {}", name)));
+        }
+        Ok(result)
+    }
+
+    /// Read a JS number as an `f64`
+    pub fn as_f64(&self) -> BareResult<f64> {
+        self.expect_type(ValueType::Number)?;
+
+        let mut result = 0.0;
+        if unsafe { js_get_value_double(self.env, self.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get number value".into()));
+        }
+        Ok(result)
+    }
+
+    /// Read a JS integer as an `i64`.
+    ///
+    /// Numbers must be integers within `Number.MAX_SAFE_INTEGER`, as larger
+    /// doubles may already have lost precision; bigints must fit in 64 bits.
+    /// Anything else is a `RangeError` rather than a silently rounded result.
+    pub fn as_i64(&self) -> BareResult<i64> {
+        if self.type_of()? == ValueType::BigInt {
+            let mut result = 0;
+            let mut lossless = false;
+            if unsafe { js_get_value_bigint_int64(self.env, self.raw, &mut result, &mut lossless) } != 0 {
+                return Err(BareError::RuntimeError("Failed to get bigint value".into()));
+            }
+            if !lossless {
+                return Err(range_error("BigInt does not fit in 64 bits".into()));
+            }
+            return Ok(result);
+        }
+
+        // js_get_value_int64 truncates fractions and saturates out of range
+        // values, so check the double itself
+        let n = self.as_f64()?;
+        if n.fract() != 0.0 || n.abs() > MAX_SAFE_INTEGER {
+            return Err(range_error(format!("{} is not a safe integer", n)));
+        }
+        Ok(n as i64)
+    }
+
+    /// Read a JS number as a `u32`, failing with a `RangeError` unless it is
+    /// an integer from 0 to 2^32 - 1
+    pub fn as_u32(&self) -> BareResult<u32> {
+        let n = self.as_f64()?;
+        if n.fract() != 0.0 || !(0.0..=u32::MAX as f64).contains(&n) {
+            return Err(range_error(format!("{} is not a valid unsigned 32-bit integer", n)));
+        }
+        Ok(n as u32)
+    }
+
+    /// Read a JS boolean as a `bool`
+    pub fn as_bool(&self) -> BareResult<bool> {
+        self.expect_type(ValueType::Boolean)?;
+
+        let mut result = false;
+        if unsafe { js_get_value_bool(self.env, self.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get boolean value".into()));
+        }
+        Ok(result)
+    }
+
+    /// Read a JS string as a UTF-8 `String`
+    pub fn as_string(&self) -> BareResult<String> {
+        self.expect_type(ValueType::String)?;
+
+        let mut str_len = 0;
+        unsafe {
+            if js_get_value_string_utf8(self.env, self.raw, ptr::null_mut(), 0, &mut str_len) != 0 {
+                return Err(BareError::RuntimeError("Failed to get string length".into()));
+            }
+
+            let mut buffer = vec![0u8; str_len + 1];
+            if js_get_value_string_utf8(self.env, self.raw, buffer.as_mut_ptr(), buffer.len(), &mut str_len) != 0 {
+                return Err(BareError::RuntimeError("Failed to get string value".into()));
+            }
+
+            Ok(String::from_utf8_lossy(&buffer[..str_len]).into_owned())
+        }
+    }
+
+    /// Copy the bytes of an `ArrayBuffer`, typed array or `DataView`.
+    ///
+    /// Views yield only the part of their buffer they cover. Anything else
+    /// is a `TypeError`.
+    pub fn as_bytes(&self) -> BareResult<Vec<u8>> {
+        self.with_bytes(|bytes| bytes.to_vec())
+    }
+
+    /// Like `as_bytes`, but lend the bytes to `f` rather than copying them.
+    ///
+    /// The slice points into the JS backing store, so `f` must not call into
+    /// JS: a script could detach or resize the buffer under it.
+    pub fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> BareResult<R> {
+        let mut data = ptr::null_mut();
+        let mut len = 0;
+
+        unsafe {
+            let status = if self.is(js_is_arraybuffer)? {
+                js_get_arraybuffer_info(self.env, self.raw, &mut data, &mut len)
+            } else if self.is(js_is_typedarray)? {
+                let mut kind = 0;
+                let status = js_get_typedarray_info(
+                    self.env, self.raw, &mut kind, &mut data, &mut len, ptr::null_mut(), ptr::null_mut(),
+                );
+                // The length is in elements
+                len *= element_size(kind);
+                status
+            } else if self.is(js_is_dataview)? {
+                js_get_dataview_info(self.env, self.raw, &mut data, &mut len, ptr::null_mut(), ptr::null_mut())
+            } else {
+                return Err(type_error(format!("Expected ArrayBuffer or view but got {}", self.type_of()?)));
+            };
+            if status != 0 {
+                return Err(BareError::RuntimeError("Failed to get buffer contents".into()));
+            }
+
+            // Empty and detached buffers may have no backing store at all
+            if data.is_null() || len == 0 {
+                return Ok(f(&[]));
+            }
+            Ok(f(std::slice::from_raw_parts(data as *const u8, len)))
+        }
+    }
+
+    // Ask one of the js_is_* predicates about the value
+    fn is(
+        &self,
+        predicate: unsafe extern "C" fn(*mut js_env_t, *mut js_value_t, *mut bool) -> std::os::raw::c_int,
+    ) -> BareResult<bool> {
+        let mut result = false;
+        if unsafe { predicate(self.env, self.raw, &mut result) } != 0 {
+            return Err(BareError::RuntimeError("Failed to check value type".into()));
+        }
+        Ok(result)
+    }
+
+    /// Like `call`, with arguments built from Rust values through `JsArg`
+    pub fn call_args(&self, args: &[JsArg]) -> BareResult<Value<'bare>> {
+        let args = args.iter()
+            .map(|arg| arg.to_value(self.env))
+            .collect::<BareResult<Vec<_>>>()?;
+        self.call(&args)
+    }
+
+    /// Call the value as a function with `args`, using `undefined` as `this`.
+    ///
+    /// An exception thrown by the function is returned as a
+    /// `BareError::JSError`.
+    pub fn call(&self, args: &[Value]) -> BareResult<Value<'bare>> {
+        self.call_with_this(Value::undefined(self.env)?, args)
+    }
+
+    // Call the value as a function with `receiver` as `this`
+    pub(crate) fn call_with_this(&self, receiver: Value, args: &[Value]) -> BareResult<Value<'bare>> {
+        self.expect_type(ValueType::Function)?;
+
+        let argv: Vec<_> = args.iter().map(|arg| arg.raw).collect();
+
+        let mut result = ptr::null_mut();
+        unsafe {
+            if js_call_function(self.env, receiver.raw, self.raw, argv.len(), argv.as_ptr(), &mut result) != 0 {
+                try_handle_js_exception(self.env)?;
+                return Err(BareError::RuntimeError("Failed to call function".into()));
+            }
+        }
+        Ok(Value { env: self.env, raw: result, bare: PhantomData })
+    }
+
+    /// Length of a JS array
+    pub fn length(&self) -> BareResult<u32> {
+        self.expect_array()?;
+
+        let mut len = 0;
+        if unsafe { js_get_array_length(self.env, self.raw, &mut len) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get array length".into()));
+        }
+        Ok(len)
+    }
+
+    /// Element `index` of a JS array, or `undefined` past the end like in JS
+    pub fn get_index(&self, index: u32) -> BareResult<Value<'bare>> {
+        self.expect_array()?;
+
+        let mut result = ptr::null_mut();
+        if unsafe { js_get_element(self.env, self.raw, index, &mut result) } != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to get array element {}", index)));
+        }
+        Ok(Value { env: self.env, raw: result, bare: PhantomData })
+    }
+
+    /// Property `name` of a JS object, or `undefined` if it isn't set
+    ///
+    /// The name is passed as a JS string, so it may contain NUL bytes.
+    pub fn get_property(&self, name: &str) -> BareResult<Value<'bare>> {
+        self.expect_object()?;
+
+        let mut key = ptr::null_mut();
+        if unsafe { js_create_string_utf8(self.env, name.as_ptr(), name.len(), &mut key) } != 0 {
+            return Err(BareError::RuntimeError("Failed to create string".into()));
+        }
+        let mut result = ptr::null_mut();
+        if unsafe { js_get_property(self.env, self.raw, key, &mut result) } != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to get property '{}'", name)));
+        }
+        Ok(Value { env: self.env, raw: result, bare: PhantomData })
+    }
+
+    /// Set property `name` of a JS object to `value`
+    pub fn set_property(&self, name: &str, value: Value) -> BareResult<()> {
+        self.expect_object()?;
+        let c_name = CString::new(name)?;
+
+        if unsafe { js_set_named_property(self.env, self.raw, c_name.as_ptr(), value.raw) } != 0 {
+            return Err(BareError::RuntimeError(format!("Failed to set property '{}'", name)));
+        }
+        Ok(())
+    }
+
+    /// Names of the own enumerable string-keyed properties of a JS object,
+    /// in `Object.keys` order.
+    ///
+    /// Like `Object.keys` and `JSON.stringify`, inherited, non-enumerable and
+    /// symbol-keyed properties are left out, and array indices come back as
+    /// strings.
+    pub fn keys(&self) -> BareResult<Vec<String>> {
+        self.expect_object()?;
+
+        let names = unsafe {
+            let mut names = ptr::null_mut();
+            if js_get_property_names(self.env, self.raw, &mut names) != 0 {
+                return Err(BareError::RuntimeError("Failed to get property names".into()));
+            }
+            Value { env: self.env, raw: names, bare: PhantomData }
+        };
+
+        let mut keys = Vec::new();
+        for i in 0..names.length()? {
+            let name = names.get_index(i)?;
+            let mut own = false;
+            if unsafe { js_has_own_property(self.env, self.raw, name.raw, &mut own) } != 0 {
+                return Err(BareError::RuntimeError("Failed to check for own property".into()));
+            }
+            if !own {
+                continue;
+            }
+
+            // Integer-like keys may come back as numbers
+            keys.push(match name.type_of()? {
+                ValueType::Number => name.as_f64()?.to_string(),
+                _ => name.as_string()?,
+            });
+        }
+        Ok(keys)
+    }
+
+    /// Convert the value into JSON, recursing into arrays and objects.
+    ///
+    /// `undefined` maps to `null`, as do `NaN` and `Infinity`, which JSON
+    /// cannot represent. Functions, symbols and bigints are a `TypeError`.
+    pub fn to_json(&self) -> BareResult<serde_json::Value> {
+        json::to_json(self)
+    }
+
+    /// Serialize the value with the engine's `JSON.stringify`, indented by
+    /// two spaces if `pretty` is set.
+    ///
+    /// Unlike `to_json`, this follows JS semantics exactly, `toJSON` methods
+    /// included. Exceptions such as a `TypeError` for a cycle or a bigint
+    /// are returned as `BareError::JSError`, and values with no JSON form,
+    /// like `undefined` or a function, are a `TypeError`.
+    pub fn stringify_json(&self, pretty: bool) -> BareResult<String> {
+        let mut global = ptr::null_mut();
+        if unsafe { js_get_global(self.env, &mut global) } != 0 {
+            return Err(BareError::RuntimeError("Failed to get global object".into()));
+        }
+
+        let json = Value { env: self.env, raw: global, bare: PhantomData }.get_property("JSON")?;
+        let mut args = vec![*self];
+        if pretty {
+            args.push(Value::undefined(self.env)?);
+            args.push(JsArg::Number(2.0).to_value(self.env)?);
+        }
+        let result = json.get_property("stringify")?.call_with_this(json, &args)?;
+        if result.type_of()? != ValueType::String {
+            return Err(type_error(format!("Cannot stringify {} as JSON", self.type_of()?)));
+        }
+        result.as_string()
+    }
+
+    /// Deserialize the value into `T` through its JSON form, e.g. to read a
+    /// script's result into a config struct.
+    ///
+    /// The conversion to JSON is the one `to_json` does, so the same values
+    /// are rejected. A JSON shape that doesn't fit `T` is a `TypeError`.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> BareResult<T> {
+        serde_json::from_value(self.to_json()?)
+            .map_err(|e| type_error(format!("Cannot deserialize value: {}", e)))
+    }
+
+    /// Format the value for logs or a REPL, roughly like `util.inspect`:
+    /// strings are quoted, arrays and objects are expanded one level deep,
+    /// functions show as `[Function: name]` and errors as their stack.
+    pub fn to_debug_string(&self) -> BareResult<String> {
+        inspect::to_debug_string(self)
+    }
+
+    // Reading a value through the wrong accessor is a type error, like in JS
+    fn expect_type(&self, expected: ValueType) -> BareResult<()> {
+        let actual = self.type_of()?;
+        if actual != expected {
+            return Err(type_error(format!("Expected {} but got {}", expected, actual)));
+        }
+        Ok(())
+    }
+
+    // Functions are objects too, and carry properties like `name`
+    fn expect_object(&self) -> BareResult<()> {
+        match self.type_of()? {
+            ValueType::Object | ValueType::Function => Ok(()),
+            other => Err(type_error(format!("Expected object but got {}", other))),
+        }
+    }
+
+    fn expect_array(&self) -> BareResult<()> {
+        let mut is_array = false;
+        if unsafe { js_is_array(self.env, self.raw, &mut is_array) } != 0 {
+            return Err(BareError::RuntimeError("Failed to check for array".into()));
+        }
+        if !is_array {
+            return Err(type_error(format!("Expected array but got {}", self.type_of()?)));
+        }
+        Ok(())
+    }
+}
+
+// Shows the JS type rather than the raw pointers, which are meaningless
+// outside the engine
+impl fmt::Debug for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Value");
+        match self.type_of() {
+            Ok(value_type) => debug.field("type", &value_type.as_str()),
+            Err(_) => debug.field("type", &"unknown"),
+        };
+        debug.finish()
+    }
+}
+
+// Size in bytes of an element of a typed array of type `kind`
+#[allow(non_upper_case_globals)]
+fn element_size(kind: js_typedarray_type_t) -> usize {
+    match kind {
+        js_typedarray_type_t_js_int16array | js_typedarray_type_t_js_uint16array => 2,
+        js_typedarray_type_t_js_int32array
+        | js_typedarray_type_t_js_uint32array
+        | js_typedarray_type_t_js_float32array => 4,
+        js_typedarray_type_t_js_float64array
+        | js_typedarray_type_t_js_bigint64array
+        | js_typedarray_type_t_js_biguint64array => 8,
+        _ => 1,
+    }
+}
+
+pub(crate) fn type_error(message: String) -> BareError {
+    BareError::JSError {
+        error_type: "TypeError".into(),
+        message,
+        stack: None,
+    }
+}
+
+pub(crate) fn range_error(message: String) -> BareError {
+    BareError::JSError {
+        error_type: "RangeError".into(),
+        message,
+        stack: None,
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+
+use crate::bindings::*;
+use crate::{BareError, BareResult, Value};
+
+/// A script running on its own thread, with its own JS environment and event
+/// loop, started by `Bare::spawn_worker`.
+///
+/// bare's C API only reports threads (through `bare_on_thread`), so workers
+/// are `Bare.Thread` instances created from JS. The worker is joined when it
+/// is dropped if `join` was never called, so its teardown always runs before
+/// the instance that spawned it is torn down.
+pub struct Worker<'bare> {
+    env: *mut js_env_t,
+    // Keeps the `Bare.Thread` object alive between calls from Rust
+    thread: *mut js_ref_t,
+    joined: bool,
+    _bare: PhantomData<&'bare ()>,
+}
+
+impl<'bare> Worker<'bare> {
+    pub(crate) unsafe fn spawn(env: *mut js_env_t, options: Value) -> BareResult<Worker<'bare>> {
+        let mut global = ptr::null_mut();
+        if js_get_global(env, &mut global) != 0 {
+            return Err(BareError::RuntimeError("Failed to get global object".into()));
+        }
+        let constructor = Value::from_raw(env, global)
+            .get_property("Bare")?
+            .get_property("Thread")?;
+
+        let filename = "[worker]";
+        let mut name = ptr::null_mut();
+        if js_create_string_utf8(env, filename.as_ptr(), filename.len(), &mut name) != 0 {
+            return Err(BareError::RuntimeError("Failed to create worker filename".into()));
+        }
+
+        let argv = [name, options.as_ptr()];
+        let mut thread = ptr::null_mut();
+        if js_new_instance(env, constructor.as_ptr(), argv.len(), argv.as_ptr(), &mut thread) != 0 {
+            crate::try_handle_js_exception(env)?;
+            return Err(BareError::RuntimeError("Failed to start worker thread".into()));
+        }
+
+        let mut reference = ptr::null_mut();
+        if js_create_reference(env, thread, 1, &mut reference) != 0 {
+            return Err(BareError::RuntimeError("Failed to create worker reference".into()));
+        }
+
+        Ok(Worker {
+            env,
+            thread: reference,
+            joined: false,
+            _bare: PhantomData,
+        })
+    }
+
+    /// Block until the worker's script and event loop have finished and its
+    /// environment has been torn down
+    pub fn join(mut self) -> BareResult<()> {
+        self.join_once()
+    }
+
+    fn join_once(&mut self) -> BareResult<()> {
+        self.joined = true;
+
+        let thread = unsafe {
+            let mut thread = ptr::null_mut();
+            if js_get_reference_value(self.env, self.thread, &mut thread) != 0 {
+                return Err(BareError::RuntimeError("Failed to get worker thread".into()));
+            }
+            Value::from_raw(self.env, thread)
+        };
+        thread.get_property("join")?.call_with_this(thread, &[])?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Worker<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("joined", &self.joined)
+            .finish()
+    }
+}
+
+impl Drop for Worker<'_> {
+    fn drop(&mut self) {
+        if !self.joined {
+            if let Err(e) = self.join_once() {
+                log::error!("Failed to join worker: {}", e);
+            }
+        }
+        if unsafe { js_delete_reference(self.env, self.thread) } != 0 {
+            log::error!("Failed to delete worker reference");
+        }
+    }
+}
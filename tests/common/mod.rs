@@ -1,67 +1,75 @@
 use std::ffi::CString;
 use std::ptr;
 use std::sync::Mutex;
-use bare_rs::{BareResult, BareError, init_runtime_once, get_runtime};
+use bare_rs::{BareResult, BareError, BareOptions, Bare, JsErrorKind, PlatformOptions, Runtime, Value};
 use bare_rs::bindings::*;
 
-// Global mutex for test synchronization
+// Shared runtime for all tests, as a process can only create one; access is
+// serialized through TEST_MUTEX. GC is exposed so tests can force collections.
+struct TestRuntime(Runtime);
+
+unsafe impl Send for TestRuntime {}
+unsafe impl Sync for TestRuntime {}
+
+// Every instance set up on TEST_RUNTIME shares its single UV loop, and libuv
+// loops are not thread-safe: two instances running at once on different test
+// threads would drive the same loop concurrently. TEST_MUTEX is therefore held
+// for the whole lifetime of a TestInstance, and only there; tests that don't
+// touch the runtime run in parallel.
 lazy_static::lazy_static! {
     static ref TEST_MUTEX: Mutex<()> = Mutex::new(());
+    static ref TEST_RUNTIME: TestRuntime = TestRuntime(
+        Runtime::with_platform_options(PlatformOptions::default().expose_gc(true))
+            .expect("Failed to create test runtime")
+    );
+}
+
+// The shared runtime, for tests of runtime-wide state
+pub fn runtime() -> &'static Runtime {
+    &TEST_RUNTIME.0
 }
 
 pub struct TestInstance {
-    pub bare: *mut bare_t,
-    pub env: *mut js_env_t,
+    pub bare: Bare<'static>,
+    // Declared after `bare` so the instance is torn down before the lock is
+    // released
     _guard: std::sync::MutexGuard<'static, ()>,
 }
 
 impl TestInstance {
     pub fn new() -> BareResult<Self> {
-        // Acquire mutex to prevent parallel test execution
-        let guard = TEST_MUTEX.lock().unwrap();
-
-        unsafe {
-            // Initialize global runtime if needed
-            init_runtime_once()?;
-            
-            // Get runtime reference
-            let runtime = get_runtime()?;
-
-            // Initialize bare runtime
-            let options = bare_options_t {
-                version: 0,
-                memory_limit: 1024 * 1024 * 1024,
-            };
-
-            let mut bare = ptr::null_mut();
-            let mut env = ptr::null_mut();
-            let args = vec![CString::new("test").unwrap()];
-            let mut c_args: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
-
-            let setup_result = bare_setup(
-                runtime.uv_loop,
-                runtime.platform,
-                &mut env,
-                c_args.len() as i32,
-                c_args.as_mut_ptr(),
-                &options,
-                &mut bare,
-            );
-
-            if setup_result != 0 {
-                return Err(BareError::SetupError("Bare setup failed".into()));
-            }
-
-            Ok(TestInstance {
-                bare,
-                env,
-                _guard: guard,
-            })
-        }
+        Self::with_args(&["test"])
+    }
+
+    // Create an instance whose scripts see `args` as `Bare.argv`
+    pub fn with_args(args: &[&str]) -> BareResult<Self> {
+        Self::setup(args, BareOptions::default())
+    }
+
+    // Create an instance set up with `options`
+    pub fn with_options(options: BareOptions) -> BareResult<Self> {
+        Self::setup(&["test"], options)
+    }
+
+    fn setup(args: &[&str], options: BareOptions) -> BareResult<Self> {
+        // Acquire mutex to prevent parallel use of the shared loop. A test
+        // that panicked while holding it has still torn its instance down, so
+        // a poisoned lock is safe to take over.
+        let guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Initialize bare runtime on the shared test runtime
+        let bare = TEST_RUNTIME.0.setup(args, options)?;
+
+        Ok(TestInstance {
+            bare,
+            _guard: guard,
+        })
     }
 
-    // Helper to run JavaScript code and expect success
-    pub unsafe fn run_script(&self, code: &str) -> BareResult<()> {
+    // Helper to load JavaScript code without running the event loop,
+    // returning the result of `bare_load` (the module exports for CommonJS
+    // sources)
+    pub unsafe fn load_script(&self, code: &str) -> BareResult<Value> {
         let script = CString::new(code).unwrap();
         let len = script.as_bytes().len();
         let source = uv_buf_t {
@@ -72,67 +80,77 @@ impl TestInstance {
         let mut result = ptr::null_mut();
 
         // Load the script
-        let load_result = bare_load(self.bare, filename.as_ptr(), &source, &mut result);
+        let load_result = bare_load(self.bare.as_ptr(), filename.as_ptr(), &source, &mut result);
         if load_result != 0 {
             return Err(BareError::RuntimeError("Failed to load script".into()));
         }
 
-        // Run the script
-        let run_result = bare_run(self.bare);
-        if run_result != 0 {
-            return Err(BareError::RuntimeError("Failed to run script".into()));
+        // Scripts that produce no value evaluate to undefined
+        if result.is_null() && js_get_undefined(self.bare.env_ptr(), &mut result) != 0 {
+            return Err(BareError::RuntimeError("Failed to get undefined".into()));
         }
 
-        // Check for exceptions
-        bare_rs::handle_js_exception(self.env)
+        Ok(Value::from_raw(self.bare.env_ptr(), result))
     }
 
-    // Helper to run JavaScript code and expect an error
-    pub unsafe fn run_script_expect_error(&self, code: &str, expected_error: &str) -> BareResult<()> {
-        let script = CString::new(code).unwrap();
-        let len = script.as_bytes().len();
-        let source = uv_buf_t {
-            base: script.as_ptr() as *mut i8,
-            len,
-        };
-        let filename = CString::new("test.js").unwrap();
-        let mut result = ptr::null_mut();
+    // Helper to run JavaScript code and expect success, returning the result
+    // of loading it
+    pub unsafe fn run_script(&self, code: &str) -> BareResult<Value> {
+        self.bare.eval_with_filename(code, "test.js")
+    }
 
-        // Load and run the script
-        let load_result = bare_load(self.bare, filename.as_ptr(), &source, &mut result);
-        if load_result != 0 {
-            return Err(BareError::RuntimeError("Failed to load script".into()));
+    // Helper to run JavaScript code and return everything it wrote to the
+    // console while running
+    pub unsafe fn run_script_capture(&self, code: &str) -> BareResult<String> {
+        let output = self.bare.capture_output()?;
+        self.run_script(code)?;
+        Ok(output.take())
+    }
+
+    // Helper to run JavaScript code and return the error it raised, whether
+    // compiling it or running it failed
+    pub unsafe fn run_script_get_error(&self, code: &str) -> BareResult<BareError> {
+        // Compile errors are raised by the load
+        if let Err(e) = self.bare.load(code, "test.js") {
+            return Ok(e);
         }
 
-        let run_result = bare_run(self.bare);
+        let run_result = bare_run(self.bare.as_ptr());
         if run_result == 0 {
             return Err(BareError::RuntimeError("Expected script to fail".into()));
         }
 
-        // Check for the expected error
-        match bare_rs::handle_js_exception(self.env) {
+        match self.bare.check_exception() {
             Ok(_) => Err(BareError::RuntimeError("Expected error but got success".into())),
-            Err(BareError::JSError { error_type, message, .. }) => {
-                let error_text = format!("{}: {}", error_type, message);
-                if error_text.contains(expected_error) {
-                    Ok(())
-                } else {
-                    Err(BareError::RuntimeError(format!(
-                        "Expected error '{}' but got '{}'",
-                        expected_error, error_text
-                    )))
-                }
-            }
-            Err(e) => Err(e),
+            Err(e) => Ok(e),
         }
     }
-}
 
-impl Drop for TestInstance {
-    fn drop(&mut self) {
-        unsafe {
-            let mut exit_code = 0;
-            bare_teardown(self.bare, &mut exit_code);
+    // Helper to run JavaScript code and expect an error of class `expected`
+    pub unsafe fn run_script_expect_kind(&self, code: &str, expected: JsErrorKind) -> BareResult<()> {
+        let error = self.run_script_get_error(code)?;
+        match error.js_error_kind() {
+            Some(kind) if kind == expected => Ok(()),
+            _ => Err(BareError::RuntimeError(format!("Expected {:?} but got {:?}", expected, error))),
         }
     }
-} 
\ No newline at end of file
+
+    // Helper to run JavaScript code and expect an error
+    pub unsafe fn run_script_expect_error(&self, code: &str, expected_error: &str) -> BareResult<()> {
+        // Check for the expected error
+        let error_text = match self.run_script_get_error(code)? {
+            BareError::JSError { error_type, message, .. } => format!("{}: {}", error_type, message),
+            BareError::CompileError { message, .. } => format!("SyntaxError: {}", message),
+            e => return Err(e),
+        };
+
+        if error_text.contains(expected_error) {
+            Ok(())
+        } else {
+            Err(BareError::RuntimeError(format!(
+                "Expected error '{}' but got '{}'",
+                expected_error, error_text
+            )))
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use bare_rs::BareResult;
+use bare_rs::{handle_js_exception_json, try_handle_js_exception, BareResult, BareError, JsErrorKind, StackFrame};
+use bare_rs::bindings::js_throw;
 use super::common::TestInstance;
 use log::debug;
 
@@ -9,9 +10,9 @@ fn test_bare_runtime_syntax_error() -> BareResult<()> {
     
     unsafe {
         // Test syntax error
-        let result = instance.run_script_expect_error(
+        let result = instance.run_script_expect_kind(
             "this is not valid javascript;",
-            "SyntaxError"
+            JsErrorKind::SyntaxError
         );
         assert!(result.is_ok(), "Expected SyntaxError but got: {:?}", result);
         Ok(())
@@ -25,9 +26,9 @@ fn test_bare_runtime_runtime_error() -> BareResult<()> {
 
     unsafe {
         // Test runtime error
-        let result = instance.run_script_expect_error(
+        let result = instance.run_script_expect_kind(
             "throw new Error('Expected error');",
-            "Error: Expected error"
+            JsErrorKind::Other("Error".into())
         );
         assert!(result.is_ok(), "Expected Error but got: {:?}", result);
         Ok(())
@@ -41,11 +42,270 @@ fn test_bare_runtime_reference_error() -> BareResult<()> {
 
     unsafe {
         // Test reference error
-        let result = instance.run_script_expect_error(
+        let result = instance.run_script_expect_kind(
             "nonexistentFunction();",
-            "ReferenceError"
+            JsErrorKind::ReferenceError
         );
         assert!(result.is_ok(), "Expected ReferenceError but got: {:?}", result);
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_bare_runtime_error_type() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    debug!("=== Starting error type test ===");
+
+    unsafe {
+        // The error type comes from the constructor name, not the message
+        let error = instance.run_script_get_error("JSON.parse('this is not valid JSON')")?;
+        assert_eq!(error.js_error_kind(), Some(JsErrorKind::SyntaxError));
+        match error {
+            BareError::JSError { error_type, .. } => assert_eq!(error_type, "SyntaxError"),
+            e => panic!("Expected SyntaxError but got: {:?}", e),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_bare_runtime_thrown_non_error() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that thrown values other than errors are reported as they are,
+    // with `typeof` as the type and `String(value)` as the message
+    let cases = [
+        ("throw 'x'", "string", "x"),
+        ("throw 42", "number", "42"),
+        ("throw {}", "Object", "[object Object]"),
+        ("throw Object.create(null)", "object", "[object]"),
+    ];
+    for (code, expected_type, expected_message) in cases {
+        match unsafe { instance.run_script_get_error(code)? } {
+            BareError::JSError { error_type, message, stack } => {
+                assert_eq!(error_type, expected_type, "{}", code);
+                assert_eq!(message, expected_message, "{}", code);
+                assert_eq!(stack.as_deref(), Some(""), "{}", code);
+            }
+            e => panic!("Expected a JSError for '{}' but got: {:?}", code, e),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_compile_error() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that compile errors are reported apart from runtime errors,
+    // naming the file
+    let error = match instance.bare.load("let x = ;", "broken.js") {
+        Err(error) => error,
+        Ok(_) => panic!("Expected loading invalid source to fail"),
+    };
+    assert_eq!(error.js_error_kind(), Some(JsErrorKind::SyntaxError));
+    assert!(error.to_string().starts_with("Compile error in broken.js"), "Unexpected error: {}", error);
+    match error {
+        BareError::CompileError { message, file, .. } => {
+            assert!(message.starts_with("Unexpected token"), "Unexpected message: {}", message);
+            assert_eq!(file, "broken.js");
+        }
+        e => panic!("Expected CompileError but got: {:?}", e),
+    }
+
+    // Test that a SyntaxError thrown at runtime stays a JS error
+    let error = unsafe { instance.run_script_get_error("JSON.parse('{')")? };
+    assert!(matches!(error, BareError::JSError { .. }), "Expected JSError but got: {:?}", error);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_module_compile_error() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that only a module file that doesn't compile is a compile error,
+    // not one whose code throws a SyntaxError
+    let dir = std::env::temp_dir().join(format!("bare-rs-compile-error-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("broken.js"), "let x = ;").unwrap();
+    std::fs::write(dir.join("throws.js"), "JSON.parse('{')").unwrap();
+
+    let broken = instance.bare.load_module(dir.join("broken.js"));
+    let throws = instance.bare.load_module(dir.join("throws.js"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    match broken {
+        Err(BareError::CompileError { file, .. }) => assert!(file.ends_with("broken.js"), "Unexpected file: {}", file),
+        result => panic!("Expected CompileError but got: {:?}", result),
+    }
+    match throws {
+        Err(BareError::JSError { error_type, .. }) => assert_eq!(error_type, "SyntaxError"),
+        result => panic!("Expected JSError but got: {:?}", result),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_check_syntax() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that valid sources compile without running
+    instance.bare.check_syntax("globalThis.checked = true; module.exports = 1", "ok.js")?;
+    instance.bare.check_syntax("export default 1", "ok.mjs")?;
+    assert!(instance.bare.get_global("checked")?.is_undefined()?);
+
+    // Test that syntax errors are reported as compile errors
+    match instance.bare.check_syntax("let x = ;", "broken.js") {
+        Err(BareError::CompileError { file, .. }) => assert_eq!(file, "broken.js"),
+        result => panic!("Expected CompileError but got: {:?}", result),
+    }
+    match instance.bare.check_syntax("export default", "broken.mjs") {
+        Err(BareError::CompileError { file, .. }) => assert_eq!(file, "broken.mjs"),
+        result => panic!("Expected CompileError but got: {:?}", result),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_allocation_failure() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    debug!("=== Starting allocation failure test ===");
+
+    unsafe {
+        // A backing store far beyond the address space can never be allocated
+        let error = instance.run_script_get_error("new ArrayBuffer(2 ** 52)")?;
+        assert!(
+            matches!(error, BareError::ResourceExhausted(_)),
+            "Expected ResourceExhausted but got: {:?}", error
+        );
+        Ok(())
+    }
+}
+
+#[test]
+fn test_bare_runtime_stack_frames() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    debug!("=== Starting stack frames test ===");
+
+    unsafe {
+        // The innermost frame points at the throwing function
+        let error = instance.run_script_get_error("function fail() {\n  throw new Error('boom')\n}\nfail()")?;
+        let frames = error.stack_frames();
+        assert!(!frames.is_empty(), "Expected stack frames in: {:?}", error);
+        assert_eq!(frames[0].function.as_deref(), Some("fail"));
+        assert!(frames[0].file.ends_with("test.js"), "Unexpected file: {}", frames[0].file);
+        assert_eq!(frames[0].line, 2);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stack_frame_parsing() {
+    let error = BareError::JSError {
+        error_type: "Error".into(),
+        message: "boom".into(),
+        stack: Some([
+            "Error: boom",
+            "    at fail (file:///app/main.js:2:9)",
+            "    at file:///app/main.js:4:1",
+            "    at Array.forEach (<anonymous>)",
+        ].join("\n")),
+    };
+
+    assert_eq!(error.stack_frames(), vec![
+        StackFrame { function: Some("fail".into()), file: "file:///app/main.js".into(), line: 2, column: 9 },
+        StackFrame { function: None, file: "file:///app/main.js".into(), line: 4, column: 1 },
+    ]);
+}
+
+#[test]
+fn test_bare_runtime_nul_byte() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    debug!("=== Starting NUL byte test ===");
+
+    // The error points at the NUL so it can be found in the script
+    match instance.bare.eval("let a = 1;\0") {
+        Err(e @ BareError::NulError(_)) => {
+            let msg = e.to_string();
+            assert!(msg.contains("byte offset 10"), "Unexpected error: {}", msg);
+            // The original error stays available to error reporting libraries
+            let source = std::error::Error::source(&e).expect("Expected a NulError source");
+            assert!(source.downcast_ref::<std::ffi::NulError>().is_some());
+        }
+        Err(e) => panic!("Expected NulError but got: {:?}", e),
+        Ok(_) => panic!("Expected a script with a NUL byte to be rejected"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_exception_json() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    let env = instance.bare.env_ptr();
+
+    unsafe {
+        // Nothing is reported without a pending exception
+        assert!(matches!(handle_js_exception_json(env), Err(BareError::RuntimeError(_))));
+
+        let error = instance.run_script("module.exports = new TypeError('bad input')")?;
+        js_throw(env, error.as_ptr());
+
+        // Test that the exception is reported as a single JSON line and cleared
+        let line = handle_js_exception_json(env)?;
+        assert!(!line.contains('\n'), "Unexpected line: {}", line);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["type"], "TypeError");
+        assert_eq!(json["message"], "bad input");
+        assert!(json["stack"].as_str().unwrap().contains("test.js"));
+        assert!(matches!(handle_js_exception_json(env), Err(BareError::RuntimeError(_))));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_exception_quiet() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    let env = instance.bare.env_ptr();
+
+    unsafe {
+        try_handle_js_exception(env)?;
+
+        let error = instance.run_script("module.exports = new RangeError('too far')")?;
+        js_throw(env, error.as_ptr());
+
+        // Test that the exception is returned and cleared, as by the logging variant
+        match try_handle_js_exception(env) {
+            Err(BareError::JSError { error_type, message, .. }) => {
+                assert_eq!(error_type, "RangeError");
+                assert_eq!(message, "too far");
+            }
+            other => panic!("Expected JSError but got: {:?}", other),
+        }
+        try_handle_js_exception(env)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_io_error() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that a missing module keeps the underlying I/O error as its source
+    match instance.bare.load_module("does/not/exist.js") {
+        Err(e @ BareError::IoError(_)) => {
+            let source = std::error::Error::source(&e).expect("Expected an I/O error source");
+            let io_error = source.downcast_ref::<std::io::Error>().expect("Expected an io::Error");
+            assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
+        }
+        Err(e) => panic!("Expected IoError but got: {:?}", e),
+        Ok(_) => panic!("Expected loading a missing module to fail"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,689 @@
+use bare_rs::{BareError, BareResult, JsArg, JsErrorKind, Value, ValueType};
+use serde_json::json;
+use super::common::TestInstance;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[test]
+fn test_global_fn() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test calling a Rust closure from JavaScript
+    instance.bare.set_global_fn("echo", |args| Ok(args[0]))?;
+
+    unsafe {
+        let result = instance.run_script("module.exports = echo(42)")?;
+        assert_eq!(result.as_f64()?, 42.0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_global_fn_panic() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that a panicking closure throws instead of unwinding into JS
+    instance.bare.set_global_fn("explode", |_| panic!("boom"))?;
+
+    unsafe {
+        instance.run_script(r#"
+            try {
+                explode();
+                throw new Error('Expected explode() to throw');
+            } catch (err) {
+                if (!err.message.includes('boom')) {
+                    throw new Error('Unexpected error: ' + err.message);
+                }
+            }
+        "#)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_round_trip() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test converting structured data into JS and back
+    let input = json!({
+        "name": "bare",
+        "count": 42,
+        "ratio": 0.5,
+        "tags": ["a", "b"],
+        "nested": { "ok": true, "missing": null }
+    });
+
+    let value = instance.bare.to_js(&input)?;
+    assert_eq!(value.to_json()?, input);
+
+    Ok(())
+}
+
+#[test]
+fn test_json_from_script() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        // Test reading a script-produced object, including undefined
+        let result = instance.run_script(r#"
+            module.exports = { items: [1, 'two', undefined], flag: false }
+        "#)?;
+        assert_eq!(result.to_json()?, json!({ "items": [1, "two", null], "flag": false }));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_own_properties() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        // Test that inherited properties are left out, as with JSON.stringify,
+        // including enumerable methods that can't be converted
+        let result = instance.run_script(r#"
+            function Point(x) { this.x = x }
+            Point.prototype.norm = function () { return Math.abs(this.x) }
+            Point.prototype.unit = 'px'
+            module.exports = new Point(-2)
+        "#)?;
+        assert_eq!(result.to_json()?, json!({ "x": -2 }));
+
+        // Test keys containing NUL bytes, both ways
+        let result = instance.run_script(r#"module.exports = { "a\0b": 1 }"#)?;
+        assert_eq!(result.to_json()?, json!({ "a\u{0}b": 1 }));
+    }
+    let value = instance.bare.to_js(&json!({ "c\u{0}d": true }))?;
+    assert_eq!(value.to_json()?, json!({ "c\u{0}d": true }));
+
+    Ok(())
+}
+
+#[test]
+fn test_get_global() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.run_script("globalThis.answer = 42")?;
+    }
+    assert_eq!(instance.bare.get_global("answer")?.as_f64()?, 42.0);
+
+    // Missing globals read as undefined rather than failing
+    assert!(instance.bare.get_global("missing")?.is_undefined()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_set_global() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test injecting configuration instead of templating it into the source
+    let config = instance.bare.to_js(&json!({ "name": "bare", "retries": 3 }))?;
+    instance.bare.set_global("config", config)?;
+
+    unsafe {
+        let result = instance.run_script("module.exports = `${config.name}:${config.retries}`")?;
+        assert_eq!(result.as_string()?, "bare:3");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_function() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.run_script("globalThis.add = function add(a, b) { return a + b }")?;
+    }
+
+    // Test calling a JS function from Rust
+    let add = instance.bare.get_global("add")?;
+    let args = [instance.bare.to_js(&json!(2))?, instance.bare.to_js(&json!(3))?];
+    assert_eq!(add.call(&args)?.as_f64()?, 5.0);
+
+    // Values that aren't functions can't be called
+    match instance.bare.get_global("missing")?.call(&[]) {
+        Err(e) => assert_eq!(e.js_error_kind(), Some(JsErrorKind::TypeError)),
+        Ok(_) => panic!("Expected calling undefined to fail"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_array_access() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test iterating a JS array without going through JSON
+    let array = unsafe { instance.run_script("module.exports = [1, 'two', true]")? };
+    assert_eq!(array.length()?, 3);
+    assert_eq!(array.get_index(0)?.as_f64()?, 1.0);
+    assert_eq!(array.get_index(1)?.as_string()?, "two");
+    assert!(array.get_index(2)?.as_bool()?);
+    assert!(array.get_index(3)?.is_undefined()?);
+
+    // Non-arrays aren't indexable
+    let number = instance.bare.to_js(&json!(42))?;
+    match number.length() {
+        Err(e) => assert_eq!(e.js_error_kind(), Some(JsErrorKind::TypeError)),
+        Ok(_) => panic!("Expected length of a number to fail"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_object_properties() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test traversing and updating a nested object from Rust
+    let object = unsafe { instance.run_script("module.exports = { nested: { name: 'bare' } }")? };
+    let nested = object.get_property("nested")?;
+    assert_eq!(nested.get_property("name")?.as_string()?, "bare");
+    assert!(nested.get_property("missing")?.is_undefined()?);
+
+    nested.set_property("count", instance.bare.to_js(&json!(3))?)?;
+    assert_eq!(object.to_json()?, json!({ "nested": { "name": "bare", "count": 3 } }));
+
+    // Primitives have no properties to read
+    let number = instance.bare.to_js(&json!(42))?;
+    match number.get_property("name") {
+        Err(e) => assert_eq!(e.js_error_kind(), Some(JsErrorKind::TypeError)),
+        Ok(_) => panic!("Expected property access on a number to fail"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_debug_output() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that debug output describes values instead of printing pointers
+    let value = instance.bare.to_js(&json!(42))?;
+    assert_eq!(format!("{:?}", value), r#"Value { type: "number" }"#);
+
+    let bare = format!("{:?}", instance.bare);
+    assert!(bare.starts_with("Bare { has_env: true, torn_down: false"), "Unexpected debug output: {}", bare);
+
+    Ok(())
+}
+
+#[test]
+fn test_instance_of() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test checking against builtin and script-defined constructors
+    let error = unsafe {
+        instance.run_script(r#"
+            globalThis.Custom = class Custom extends TypeError {}
+            module.exports = new Custom('custom')
+        "#)?
+    };
+    assert!(error.instance_of("Error")?);
+    assert!(error.instance_of("TypeError")?);
+    assert!(error.instance_of("Custom")?);
+    assert!(!error.instance_of("RangeError")?);
+
+    // Primitives aren't instances of anything
+    assert!(!instance.bare.to_js(&json!(42))?.instance_of("Number")?);
+
+    // Only constructors can be checked against
+    match error.instance_of("missing") {
+        Err(e) => assert_eq!(e.js_error_kind(), Some(JsErrorKind::TypeError)),
+        Ok(_) => panic!("Expected instanceof an undefined global to fail"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_drain_microtasks() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.run_script(r#"
+            globalThis.settled = false
+            globalThis.settle = () => { Promise.resolve().then(() => { globalThis.settled = true }) }
+        "#)?;
+    }
+
+    // Test that reactions queued by a call from Rust have run once drained
+    instance.bare.get_global("settle")?.call(&[])?;
+    instance.bare.drain_microtasks()?;
+    assert!(instance.bare.get_global("settled")?.as_bool()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_constructors() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    let bare = &instance.bare;
+
+    assert_eq!(Value::new_string(bare, "héllo")?.as_string()?, "héllo");
+    assert_eq!(Value::new_number(bare, 3.5)?.as_f64()?, 3.5);
+    assert!(Value::new_bool(bare, true)?.as_bool()?);
+    assert!(Value::new_null(bare)?.is_null()?);
+    assert!(Value::new_undefined(bare)?.is_undefined()?);
+
+    // Test passing Rust-created arguments to a JS function
+    let join = unsafe { instance.run_script("module.exports = (...args) => args.map(String).join(',')")? };
+    let args = [
+        Value::new_string(bare, "x")?,
+        Value::new_number(bare, 3.0)?,
+        Value::new_bool(bare, false)?,
+        Value::new_null(bare)?,
+    ];
+    let result = join.call(&args)?;
+    assert_eq!(result.type_of()?, ValueType::String);
+    assert_eq!(result.as_string()?, "x,3,false,null");
+
+    Ok(())
+}
+
+#[test]
+fn test_import_json_module() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    instance.bare.import_json_module("config", &json!({ "name": "app", "port": 8080 }))?;
+
+    // Test that scripts can require the registered module
+    let result = unsafe {
+        instance.run_script(r#"
+            const config = require('config')
+            module.exports = `${config.name}:${config.port}`
+        "#)?
+    };
+    assert_eq!(result.as_string()?, "app:8080");
+
+    // Test that other specifiers still resolve as usual
+    match unsafe { instance.run_script("require('not-a-registered-module')") } {
+        Err(_) => {}
+        Ok(_) => panic!("Expected an unregistered module to fail to resolve"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_module_resolver() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    instance.bare.set_module_resolver(|specifier, _referrer| match specifier {
+        "greeting" => Ok(Some("module.exports = (name) => `hello ${name}`".into())),
+        "broken" => Err(BareError::RuntimeError("no such module in the bundle".into())),
+        _ => Ok(None),
+    })?;
+
+    // Test that the resolver serves modules from memory
+    let result = unsafe {
+        instance.run_script(r#"
+            const greet = require('greeting')
+            module.exports = greet('world')
+        "#)?
+    };
+    assert_eq!(result.as_string()?, "hello world");
+
+    // Test that resolver errors are thrown to the requiring script
+    let error = unsafe { instance.run_script_get_error("require('broken')")? };
+    assert!(error.to_string().contains("no such module in the bundle"));
+
+    // Test that unanswered specifiers fall back to bare's resolution
+    match unsafe { instance.run_script("require('not-a-bundled-module')") } {
+        Err(_) => {}
+        Ok(_) => panic!("Expected an unknown module to fail to resolve"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_js_args() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.run_script("globalThis.describe = (...args) => args.map((arg) => `${typeof arg}:${arg}`).join(' ')")?;
+    }
+
+    // Test that each kind of argument reaches JS as the matching type
+    let name = Value::new_string(&instance.bare, "value")?;
+    let args: [JsArg; 7] = [1.5.into(), 2.into(), "text".into(), true.into(), JsArg::Null, json!([1, 2]).into(), name.into()];
+    let result = instance.bare.call_global("describe", &args)?;
+    assert_eq!(result, json!("number:1.5 number:2 string:text boolean:true object:null object:1,2 string:value"));
+
+    // Test calling a function value the same way
+    let describe = instance.bare.get_global("describe")?;
+    assert_eq!(describe.call_args(&[false.into()])?.as_string()?, "boolean:false");
+
+    Ok(())
+}
+
+#[test]
+fn test_to_debug_string() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let value = unsafe {
+        instance.run_script(r#"
+            module.exports = {
+                name: 'app',
+                count: 3,
+                ready: true,
+                missing: undefined,
+                tags: ['a', 'b'],
+                nested: { deep: 1 },
+                'dashed-key': null,
+                handler: function onEvent() {},
+            }
+        "#)?
+    };
+    assert_eq!(
+        value.to_debug_string()?,
+        "{ name: 'app', count: 3, ready: true, missing: undefined, tags: [Array], \
+         nested: [Object], 'dashed-key': null, handler: [Function: onEvent] }"
+    );
+
+    let array = unsafe { instance.run_script("module.exports = [1, 'two', [3], () => {}, Symbol('s'), 10n]")? };
+    assert_eq!(array.to_debug_string()?, "[ 1, 'two', [Array], [Function (anonymous)], Symbol(s), 10n ]");
+
+    // Errors show their stack, which starts with the message
+    let error = unsafe { instance.run_script("module.exports = new RangeError('out of range')")? };
+    assert!(error.to_debug_string()?.starts_with("RangeError: out of range\n"));
+
+    assert_eq!(instance.bare.to_js(&json!("it's"))?.to_debug_string()?, "'it\\'s'");
+    assert_eq!(instance.bare.to_js(&json!([]))?.to_debug_string()?, "[]");
+
+    Ok(())
+}
+
+#[test]
+fn test_integer_access() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    let bare = &instance.bare;
+
+    // Test that ids up to 2^53 - 1 survive exactly
+    let id = unsafe { instance.run_script("module.exports = Number.MAX_SAFE_INTEGER")? };
+    assert_eq!(id.as_i64()?, 9_007_199_254_740_991);
+    assert_eq!(Value::new_number(bare, -42.0)?.as_i64()?, -42);
+    let big = unsafe { instance.run_script("module.exports = -(2n ** 62n)")? };
+    assert_eq!(big.as_i64()?, -(1 << 62));
+    assert_eq!(Value::new_number(bare, 4_294_967_295.0)?.as_u32()?, u32::MAX);
+
+    // Test that lossy conversions are range errors
+    let lossy = [
+        Value::new_number(bare, 1.5)?.as_i64(),
+        Value::new_number(bare, 2f64.powi(60))?.as_i64(),
+        unsafe { instance.run_script("module.exports = 2n ** 64n")? }.as_i64(),
+    ];
+    for result in lossy {
+        assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::RangeError));
+    }
+    for n in [-1.0, 4_294_967_296.0, 0.5] {
+        let result = Value::new_number(bare, n)?.as_u32();
+        assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::RangeError));
+    }
+
+    // Non-numbers are still type errors
+    let result = Value::new_string(bare, "1")?.as_i64();
+    assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_freeze_global() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    instance.bare.set_global("config", instance.bare.to_js(&json!({ "port": 80, "tls": { "enabled": false } }))?)?;
+    instance.bare.set_global("deepConfig", instance.bare.to_js(&json!({ "tls": { "enabled": false } }))?)?;
+    instance.bare.freeze_global("config")?;
+    instance.bare.freeze_global_deep("deepConfig")?;
+
+    // Test that scripts can neither mutate nor replace frozen globals
+    let result = unsafe {
+        instance.run_script(r#"
+            config.port = 443
+            globalThis.config = {}
+            delete globalThis.config
+            config.tls.enabled = true
+            deepConfig.tls.enabled = true
+            module.exports = [config.port, config.tls.enabled, deepConfig.tls.enabled]
+        "#)?
+    };
+    assert_eq!(result.to_json()?, json!([80, true, false]));
+
+    // Strict mode code gets an error instead of a silent no-op
+    let error = unsafe { instance.run_script_get_error("'use strict'; config.port = 443")? };
+    assert_eq!(error.js_error_kind(), Some(JsErrorKind::TypeError));
+
+    // Only defined globals can be frozen
+    let result = instance.bare.freeze_global("missing");
+    assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_keys() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that only own enumerable keys come back, in order
+    let object = unsafe {
+        instance.run_script(r#"
+            const base = { inherited: 1 }
+            const object = Object.create(base)
+            object.name = 'app'
+            object[2] = 'two'
+            Object.defineProperty(object, 'hidden', { value: 1, enumerable: false })
+            object[Symbol('tag')] = 1
+            module.exports = object
+        "#)?
+    };
+    assert_eq!(object.keys()?, vec!["2", "name"]);
+
+    let array = unsafe { instance.run_script("module.exports = ['a', 'b']")? };
+    assert_eq!(array.keys()?, vec!["0", "1"]);
+
+    // Test that primitives have no keys to list
+    let result = Value::new_number(&instance.bare, 1.0)?.keys();
+    assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_stringify_json() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test the serialized form, compact and pretty
+    let data = instance.bare.eval("module.exports = { test: 'value', number: 42, list: [1, 2] }")?;
+    assert_eq!(data.stringify_json(false)?, r#"{"test":"value","number":42,"list":[1,2]}"#);
+    assert_eq!(
+        data.stringify_json(true)?,
+        "{\n  \"test\": \"value\",\n  \"number\": 42,\n  \"list\": [\n    1,\n    2\n  ]\n}"
+    );
+
+    // Test that toJSON is honored
+    let date = instance.bare.eval("module.exports = new Date(0)")?;
+    assert_eq!(date.stringify_json(false)?, r#""1970-01-01T00:00:00.000Z""#);
+
+    // Test values with no JSON form and values JSON.stringify throws on
+    let undefined = instance.bare.eval("module.exports = undefined")?;
+    assert_eq!(undefined.stringify_json(false).unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+    let cycle = instance.bare.eval("const cycle = {}; cycle.self = cycle; module.exports = cycle")?;
+    assert_eq!(cycle.stringify_json(false).unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_deserialize() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test pulling typed Rust values straight out of a script result
+    let config = unsafe {
+        instance.run_script("module.exports = { hosts: ['a', 'b'], ports: { http: 80, https: 443 } }")?
+    };
+    let hosts: Vec<String> = config.get_property("hosts")?.deserialize()?;
+    assert_eq!(hosts, vec!["a", "b"]);
+    let ports: HashMap<String, u16> = config.get_property("ports")?.deserialize()?;
+    assert_eq!(ports["https"], 443);
+
+    // Test that a mismatched shape is a TypeError
+    let result = config.get_property("hosts")?.deserialize::<Vec<u16>>();
+    assert_eq!(result.unwrap_err().js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_global() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.run_script(r#"
+            globalThis.summarize = (order, discount) => ({
+                total: order.items.reduce((sum, item) => sum + item.price, 0) * (1 - discount),
+                count: order.items.length,
+            })
+            globalThis.fail = () => { throw new RangeError('bad order') }
+            globalThis.notAFunction = 42
+        "#)?;
+    }
+
+    // Test calling a function with data and getting data back
+    let order = json!({ "items": [{ "price": 10 }, { "price": 30 }] });
+    let summary = instance.bare.call_global("summarize", &[order.into(), 0.5.into()])?;
+    assert_eq!(summary, json!({ "total": 20, "count": 2 }));
+
+    // Test that thrown exceptions and non-functions are errors
+    let error = instance.bare.call_global("fail", &[]).unwrap_err();
+    assert_eq!(error.js_error_kind(), Some(JsErrorKind::RangeError));
+    for name in ["notAFunction", "missing"] {
+        let error = instance.bare.call_global(name, &[]).unwrap_err();
+        assert_eq!(error.js_error_kind(), Some(JsErrorKind::TypeError));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_value_as_bytes() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let value = unsafe {
+        instance.run_script(r#"
+            const buffer = new Uint8Array([1, 2, 3, 4, 5, 6, 7, 8]).buffer
+            module.exports = {
+                buffer,
+                view: new Uint8Array(buffer, 2, 3),
+                wide: new Uint16Array(buffer, 4, 2),
+                dataView: new DataView(buffer, 6),
+                empty: new ArrayBuffer(0),
+                text: 'not bytes'
+            }
+        "#)?
+    };
+
+    // Test that buffers and views read only the bytes they cover
+    assert_eq!(value.get_property("buffer")?.as_bytes()?, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(value.get_property("view")?.as_bytes()?, vec![3, 4, 5]);
+    assert_eq!(value.get_property("wide")?.as_bytes()?, vec![5, 6, 7, 8]);
+    assert_eq!(value.get_property("dataView")?.as_bytes()?, vec![7, 8]);
+    assert!(value.get_property("empty")?.as_bytes()?.is_empty());
+
+    // Test borrowing the bytes without a copy
+    let sum = value.get_property("buffer")?.with_bytes(|bytes| bytes.iter().map(|&b| b as u32).sum::<u32>())?;
+    assert_eq!(sum, 36);
+
+    // Test that other values are a TypeError
+    let error = value.get_property("text")?.as_bytes().unwrap_err();
+    assert_eq!(error.js_error_kind(), Some(JsErrorKind::TypeError));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_from_bytes() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that scripts receive the bytes as a Uint8Array
+    let mut bytes = vec![0x68, 0x69, 0x21];
+    let array = Value::from_bytes(&instance.bare, &bytes)?;
+    bytes[0] = 0;
+    let global = instance.bare.get_global("globalThis")?;
+    global.set_property("input", array)?;
+    let result = unsafe {
+        instance.run_script("module.exports = input instanceof Uint8Array && new TextDecoder().decode(input)")?
+    };
+    assert_eq!(result.as_string()?, "hi!");
+
+    // Test the round trip, including an empty array
+    assert_eq!(array.as_bytes()?, vec![0x68, 0x69, 0x21]);
+    assert!(Value::from_bytes(&instance.bare, &[])?.as_bytes()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_define_class() -> BareResult<()> {
+    // Rust value behind each JS Counter, counting its own drop
+    struct Counter {
+        step: f64,
+        total: Rc<Cell<f64>>,
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl Drop for Counter {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    let instance = TestInstance::new()?;
+    let total = Rc::new(Cell::new(0.0));
+    let drops = Rc::new(Cell::new(0));
+
+    let (counter_total, counter_drops) = (total.clone(), drops.clone());
+    instance.bare.define_class("Counter", move |args| {
+        Ok(Counter { step: args[0].as_f64()?, total: counter_total.clone(), drops: counter_drops.clone() })
+    })
+        .method("tick", |counter, args| {
+            counter.total.set(counter.total.get() + counter.step);
+            Ok(args[0])
+        })
+        .register()?;
+
+    // Test that instances carry their own Rust value into methods
+    let result = unsafe {
+        instance.run_script(r#"
+            const a = new Counter(1)
+            const b = new Counter(10)
+            a.tick(); b.tick()
+            module.exports = a instanceof Counter && a.tick('ok')
+        "#)?
+    };
+    assert_eq!(result.as_string()?, "ok");
+    assert_eq!(total.get(), 12.0);
+
+    // Test that misuse is a TypeError rather than undefined behavior
+    for code in [
+        "Counter(1)",
+        "Counter.prototype.tick.call({})",
+        "Counter.prototype.tick.call(Object.create(Counter.prototype))",
+    ] {
+        let error = unsafe { instance.run_script_get_error(code)? };
+        assert_eq!(error.js_error_kind(), Some(JsErrorKind::TypeError), "Unexpected error for {}: {}", code, error);
+    }
+
+    // Test that every Rust value is dropped by teardown at the latest
+    drop(instance);
+    assert_eq!(drops.get(), 2);
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
 pub mod common;
 pub mod runtime;
-pub mod errors; 
\ No newline at end of file
+pub mod errors;
+pub mod interop;
\ No newline at end of file
@@ -1,7 +1,10 @@
-use bare_rs::{BareResult, set_stack_size};
+use bare_rs::{Bare, BareResult, BareError, BareOptions, Runtime, run_with_stack_size, DEFAULT_MEMORY_LIMIT, DEFAULT_STACK_SIZE, SANDBOX_GLOBALS};
 use bare_rs::bindings::{bare_t, bare_on_before_exit, bare_on_exit, bare_on_idle};
-use super::common::TestInstance;
+use super::common::{self, TestInstance};
 use log::debug;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
 
 // Test callbacks
 unsafe extern "C" fn test_before_exit_cb(_bare: *mut bare_t) {
@@ -18,25 +21,23 @@ unsafe extern "C" fn test_idle_cb(_bare: *mut bare_t) {
 
 #[test]
 fn test_bare_runtime_basic() -> BareResult<()> {
-    // Set larger stack size first
-    set_stack_size()?;
-
     let instance = TestInstance::new()?;
 
     unsafe {
         // Register event handlers
-        bare_on_before_exit(instance.bare, Some(test_before_exit_cb));
-        bare_on_exit(instance.bare, Some(test_exit_cb));
-        bare_on_idle(instance.bare, Some(test_idle_cb));
+        bare_on_before_exit(instance.bare.as_ptr(), Some(test_before_exit_cb));
+        bare_on_exit(instance.bare.as_ptr(), Some(test_exit_cb));
+        bare_on_idle(instance.bare.as_ptr(), Some(test_idle_cb));
 
         // Test basic arithmetic and console output
-        instance.run_script(r#"
+        let output = instance.run_script_capture(r#"
             console.log('Running basic arithmetic test...');
             let x = 1 + 1;
             if(x !== 2) throw new Error('Math is broken!');
             console.log('Basic arithmetic test passed');
             Bare.exit(0);
         "#)?;
+        assert!(output.contains("Basic arithmetic test passed"), "Unexpected output: {}", output);
     }
 
     Ok(())
@@ -62,6 +63,378 @@ fn test_bare_runtime_json() -> BareResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_bare_runtime_script_result() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        // Test reading the evaluated result back into Rust
+        let result = instance.run_script("module.exports = 40 + 2")?;
+        assert_eq!(result.as_f64()?, 42.0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_run_once() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        instance.load_script("let ticks = 0; const t = setInterval(() => { if (++ticks === 3) clearInterval(t) }, 1)")?;
+    }
+
+    // Test driving the loop one iteration at a time until it runs dry
+    let mut iterations = 0;
+    while instance.bare.run_once()? {
+        iterations += 1;
+        assert!(iterations < 1000, "event loop never ran dry");
+    }
+    assert!(iterations > 0);
+    instance.bare.run()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_load_module() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that requires resolve relative to the module, not the working directory
+    let dir = std::env::temp_dir().join(format!("bare-rs-load-module-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("util.js"), "module.exports = (a, b) => a + b").unwrap();
+    std::fs::write(dir.join("main.js"), "const add = require('./util')\nmodule.exports = add(40, 2)").unwrap();
+
+    let result = instance.bare.load_module(dir.join("main.js"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(result?.as_f64()?, 42.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_preload() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let dir = std::env::temp_dir().join(format!("bare-rs-preload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.js"), "console.log('hello')\nmodule.exports = greeting").unwrap();
+
+    // Test that the preload's globals and patches are visible to the module
+    let result = instance.bare.run_file_with_preload(r#"
+        globalThis.greeting = 'hi'
+        globalThis.logged = []
+        console.log = (message) => logged.push(message)
+    "#, dir.join("main.js"));
+
+    // Test that a failing preload keeps the module from running
+    let error = instance.bare.run_file_with_preload("throw new Error('bootstrap failed')", dir.join("main.js"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(result?.as_string()?, "hi");
+    assert_eq!(instance.bare.get_global("logged")?.to_json()?, serde_json::json!(["hello"]));
+    match error {
+        Err(BareError::JSError { message, stack, .. }) => {
+            assert_eq!(message, "bootstrap failed");
+            assert!(stack.unwrap_or_default().contains("[preload]"));
+        }
+        other => panic!("Expected JSError but got: {:?}", other),
+    }
+    assert_eq!(instance.bare.get_global("logged")?.length()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_sandbox() -> BareResult<()> {
+    let instance = TestInstance::with_options(BareOptions::default().sandbox())?;
+
+    // Test that escape hatches are gone while the basics keep working
+    let result = unsafe {
+        instance.run_script(r#"
+            module.exports = {
+                exit: typeof Bare.exit,
+                addon: typeof Bare.Addon,
+                thread: typeof Bare.Thread,
+                argv: typeof Bare.argv,
+                gc: typeof gc,
+                json: JSON.stringify([1, new URL('file:///a').protocol, typeof setTimeout]),
+            }
+        "#)?
+    };
+    assert_eq!(result.to_json()?, serde_json::json!({
+        "exit": "undefined",
+        "addon": "undefined",
+        "thread": "undefined",
+        "argv": "undefined",
+        "gc": "undefined",
+        "json": "[1,\"file:\",\"function\"]",
+    }));
+
+    // Test that only allow-listed globals remain
+    let globals: Vec<String> = unsafe {
+        instance.run_script("module.exports = Object.getOwnPropertyNames(globalThis)")?
+    }.deserialize()?;
+    for name in &globals {
+        assert!(SANDBOX_GLOBALS.contains(&name.as_str()), "Unexpected global: {}", name);
+    }
+
+    // Test that the embedder can still shut the instance down
+    instance.bare.shutdown(3)?;
+    assert_eq!(instance.bare.exit_code(), Some(3));
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_exit_code() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    assert_eq!(instance.bare.exit_code(), None);
+
+    // Test that the code requested by the script is visible before teardown,
+    // and that exiting returns to Rust without running pending work
+    unsafe {
+        instance.run_script(r#"
+            setTimeout(() => { globalThis.late = true }, 0)
+            Bare.exit(5)
+        "#)?;
+    }
+    assert_eq!(instance.bare.exit_code(), Some(5));
+    assert!(instance.bare.get_global("late")?.is_undefined()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_shutdown() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let recorded = events.clone();
+    instance.bare.set_global_fn("record", move |args| {
+        recorded.borrow_mut().push(args[0].as_string()?);
+        Ok(args[0])
+    })?;
+    unsafe {
+        instance.run_script(r#"
+            Bare.on('beforeExit', (code) => record(`beforeExit ${code}`))
+            Bare.on('exit', (code) => record(`exit ${code}`))
+        "#)?;
+    }
+    // The loop emptying already emitted beforeExit once
+    events.borrow_mut().clear();
+
+    // Test that the script's handlers run with the requested code
+    instance.bare.shutdown(3)?;
+    assert_eq!(*events.borrow(), vec!["beforeExit 3".to_string(), "exit 3".to_string()]);
+    assert_eq!(instance.bare.exit_code(), Some(3));
+
+    // Test that shutting down an exited instance does nothing
+    instance.bare.shutdown(4)?;
+    assert_eq!(events.borrow().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_single_platform() -> BareResult<()> {
+    // Creating the instance makes sure the shared test runtime exists
+    let _instance = TestInstance::new()?;
+
+    // Test that a second platform is refused rather than aborting in V8
+    match Runtime::new() {
+        Err(BareError::RuntimeError(msg)) => assert!(msg.contains("only one Runtime"), "Unexpected error: {}", msg),
+        Err(e) => panic!("Expected RuntimeError but got: {:?}", e),
+        Ok(_) => panic!("Expected a second Runtime to be refused"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_argv() -> BareResult<()> {
+    let instance = TestInstance::with_args(&["test", "app.js", "--flag", "value"])?;
+
+    // Test that the arguments given to setup reach the script
+    unsafe {
+        instance.run_script(r#"
+            const [, script, ...args] = Bare.argv;
+            if (script !== 'app.js') throw new Error(`Unexpected script: ${script}`);
+            if (args.join(' ') !== '--flag value') throw new Error(`Unexpected args: ${args}`);
+        "#)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_profile_disabled() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // The shared test runtime is created without the sampling profiler
+    match instance.bare.take_profile() {
+        Err(BareError::RuntimeError(msg)) => assert!(msg.contains("not enabled"), "Unexpected error: {}", msg),
+        Err(e) => panic!("Expected RuntimeError but got: {:?}", e),
+        Ok(_) => panic!("Expected take_profile to fail without the profiler"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_inspector_disabled() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // The shared test runtime is created without the inspector
+    assert_eq!(instance.bare.inspector_url(), None);
+    match instance.bare.wait_for_debugger("app.js") {
+        Err(BareError::RuntimeError(msg)) => assert!(msg.contains("not enabled"), "Unexpected error: {}", msg),
+        Err(e) => panic!("Expected RuntimeError but got: {:?}", e),
+        Ok(_) => panic!("Expected wait_for_debugger to fail without the inspector"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_collect_garbage() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that collections can be forced from Rust and from scripts
+    unsafe {
+        instance.run_script("globalThis.garbage = new Array(100000).fill({})")?;
+        instance.run_script("globalThis.garbage = null; gc()")?;
+    }
+    instance.bare.collect_garbage()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_load_then_run() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that loading returns the exports without running scheduled work
+    let exports = instance.bare.load(r#"
+        globalThis.ran = false
+        setTimeout(() => { globalThis.ran = true }, 0)
+        module.exports = 42
+    "#, "loaded.js")?;
+    assert_eq!(exports.as_f64()?, 42.0);
+    assert!(!instance.bare.get_global("ran")?.as_bool()?);
+
+    // Test that running the loop afterwards completes the module's work
+    instance.bare.run()?;
+    assert!(instance.bare.get_global("ran")?.as_bool()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_heap_stats() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let before = instance.bare.heap_stats()?;
+    assert!(before.used_heap_size > 0);
+    assert!(before.used_heap_size <= before.total_heap_size);
+    assert_eq!(before.heap_size_limit, Some(DEFAULT_MEMORY_LIMIT));
+
+    // Test that array buffer backing stores count as external memory
+    unsafe {
+        instance.run_script("globalThis.buffer = new ArrayBuffer(16 * 1024 * 1024)")?;
+    }
+    let after = instance.bare.heap_stats()?;
+    assert!(after.external_memory >= before.external_memory + 16 * 1024 * 1024,
+        "Unexpected external memory: {:?} then {:?}", before, after);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_eval() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test the one-shot helper without going through the test harness
+    let result = instance.bare.eval("module.exports = 'hello'")?;
+    assert_eq!(result.as_string()?, "hello");
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_uncaught_exception_handler() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let messages = Rc::new(RefCell::new(Vec::new()));
+    let recorded = messages.clone();
+    instance.bare.set_uncaught_exception_handler(move |error| {
+        let message = error.get_property("message").and_then(|m| m.as_string()).unwrap_or_default();
+        let handled = message != "fatal";
+        recorded.borrow_mut().push(message);
+        handled
+    })?;
+
+    // Test that a handled exception lets the loop keep running
+    unsafe {
+        instance.run_script("setTimeout(() => { throw new Error('boom') }, 0)")?;
+    }
+    assert_eq!(*messages.borrow(), vec!["boom".to_string()]);
+
+    // Test that a declined exception is reported as an error
+    let result = unsafe { instance.run_script("setTimeout(() => { throw new Error('fatal') }, 0)") };
+    match result {
+        Err(BareError::JSError { message, .. }) => assert_eq!(message, "fatal"),
+        Err(e) => panic!("Expected JSError but got: {:?}", e),
+        Ok(_) => panic!("Expected the declined exception to be returned"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_worker() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that a worker runs its own event loop to completion before join returns
+    let worker = instance.bare.spawn_worker(r#"
+        setTimeout(() => { globalThis.done = true }, 10)
+    "#)?;
+    worker.join()?;
+
+    // Test that a worker dropped without joining is still cleaned up
+    let worker = instance.bare.spawn_worker("const x = 1 + 1")?;
+    drop(worker);
+
+    // Workers don't share state with the instance that spawned them
+    let done = unsafe { instance.run_script("module.exports = typeof globalThis.done")? };
+    assert_eq!(done.as_string()?, "undefined");
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_stack_size() -> BareResult<()> {
+    // Test that the closure's result and errors are passed through
+    assert_eq!(run_with_stack_size(DEFAULT_STACK_SIZE, || Ok(42))?, 42);
+    match run_with_stack_size(DEFAULT_STACK_SIZE, || Err::<(), _>(BareError::RuntimeError("failed".into()))) {
+        Err(BareError::RuntimeError(msg)) => assert_eq!(msg, "failed"),
+        other => panic!("Expected RuntimeError but got: {:?}", other),
+    }
+
+    // Test that the closure runs on the dedicated thread
+    let name = run_with_stack_size(8 * 1024 * 1024, || Ok(std::thread::current().name().map(String::from)))?;
+    assert_eq!(name.as_deref(), Some("bare-rs"));
+
+    // Test that stacks too small for V8 are refused
+    match run_with_stack_size(64 * 1024, || Ok(())) {
+        Err(BareError::SetupError(msg)) => assert!(msg.contains("too small"), "Unexpected error: {}", msg),
+        other => panic!("Expected SetupError but got: {:?}", other),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_bare_runtime_error_handling() -> BareResult<()> {
     let instance = TestInstance::new()?;
@@ -131,10 +504,126 @@ fn test_bare_runtime_events() -> BareResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_bare_runtime_event_closures() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that closures can capture state and receive the exit code
+    let exit_code = Rc::new(Cell::new(None));
+    let recorded = exit_code.clone();
+    instance.bare.on_exit(move |code| recorded.set(Some(code)))?;
+
+    unsafe {
+        instance.run_script("Bare.exitCode = 3")?;
+    }
+
+    assert_eq!(instance.bare.teardown()?, 3);
+    assert_eq!(exit_code.get(), Some(3));
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_teardown_once() -> BareResult<()> {
+    // Test that an instance dropped on an error path is torn down once
+    let teardowns = Rc::new(Cell::new(0));
+    {
+        let instance = TestInstance::new()?;
+        let counted = teardowns.clone();
+        instance.bare.on_teardown(move || counted.set(counted.get() + 1))?;
+
+        let result = unsafe { instance.run_script("throw new Error('failed')") };
+        assert!(result.is_err());
+    }
+    assert_eq!(teardowns.get(), 1);
+
+    // Test that an explicit teardown isn't repeated when the handle goes away
+    let teardowns = Rc::new(Cell::new(0));
+    {
+        let instance = TestInstance::new()?;
+        let counted = teardowns.clone();
+        instance.bare.on_teardown(move || counted.set(counted.get() + 1))?;
+
+        instance.bare.teardown()?;
+        assert_eq!(teardowns.get(), 1);
+    }
+    assert_eq!(teardowns.get(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_event_panic() -> BareResult<()> {
+    // Test that a panicking handler stops the instance instead of unwinding
+    // into bare
+    let instance = TestInstance::new()?;
+    instance.bare.on_before_exit(|| panic!("boom"))?;
+
+    match unsafe { instance.run_script("globalThis.ran = true") } {
+        Err(BareError::RuntimeError(message)) => {
+            assert_eq!(message, "beforeExit handler panicked: boom");
+        }
+        other => panic!("Expected RuntimeError but got: {:?}", other),
+    }
+
+    // Test that a panic during teardown doesn't escape either
+    let instance = TestInstance::new()?;
+    instance.bare.on_teardown(|| panic!("boom"))?;
+    instance.bare.teardown()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_capture_output() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test capturing console output instead of printing it
+    let output = instance.bare.capture_output()?;
+
+    unsafe {
+        instance.run_script(r#"
+            console.log('Captured', 42, { ok: true });
+            console.error('Captured error');
+        "#)?;
+    }
+
+    assert_eq!(output.take(), "Captured 42 {\"ok\":true}\nCaptured error\n");
+    assert_eq!(output.take(), "");
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_timeout() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    unsafe {
+        // Test that a runaway callback is terminated at the deadline
+        instance.load_script("setTimeout(() => { while (true) {} }, 0)")?;
+    }
+
+    let result = instance.bare.run_with_timeout(Duration::from_millis(100));
+    assert!(
+        matches!(result, Err(BareError::ResourceExhausted(_))),
+        "Expected timeout but got: {:?}", result
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_bare_runtime_async() -> BareResult<()> {
     let instance = TestInstance::new()?;
 
+    // A failed check in the `.then` callback rejects a promise nobody
+    // awaits, so collect rejections rather than lose them
+    let rejections = Rc::new(RefCell::new(Vec::new()));
+    let sink = rejections.clone();
+    instance.bare.on_unhandled_rejection(move |reason| {
+        sink.borrow_mut().push(reason.to_debug_string().unwrap_or_default());
+    })?;
+
     unsafe {
         // Test async operations
         instance.run_script(r#"
@@ -159,6 +648,39 @@ fn test_bare_runtime_async() -> BareResult<()> {
             });
         "#)?;
     }
+    assert!(rejections.borrow().is_empty(), "Unhandled rejections: {:?}", rejections.borrow());
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_rust_timers() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let recorded = order.clone();
+    instance.bare.set_global_fn("record", move |args| {
+        recorded.borrow_mut().push(args[0].as_string()?);
+        Ok(args[0])
+    })?;
+
+    // Test that Rust timers fire in order with JS timers on the same loop
+    let recorded = order.clone();
+    instance.bare.set_timeout(Duration::from_millis(20), move || recorded.borrow_mut().push("rust".into()))?;
+    unsafe {
+        instance.run_script(r#"
+            setTimeout(() => record('js early'), 5)
+            setTimeout(() => record('js late'), 60)
+        "#)?;
+    }
+    assert_eq!(*order.borrow(), vec!["js early", "rust", "js late"]);
+
+    // Test that timers still pending at teardown never fire
+    let fired = Rc::new(Cell::new(false));
+    let flag = fired.clone();
+    instance.bare.set_timeout(Duration::from_secs(60), move || flag.set(true))?;
+    instance.bare.teardown()?;
+    assert!(!fired.get());
 
     Ok(())
 }
@@ -169,7 +691,7 @@ fn test_bare_runtime_memory() -> BareResult<()> {
 
     unsafe {
         // Test memory operations
-        instance.run_script(r#"
+        let output = instance.run_script_capture(r#"
             // Allocate and manipulate a large array
             const array = new Array(1000).fill(0);
             array.forEach((_, i) => array[i] = i);
@@ -184,7 +706,204 @@ fn test_bare_runtime_memory() -> BareResult<()> {
             console.log('Memory test passed');
             Bare.exit(0);
         "#)?;
+        assert!(output.contains("Memory test passed"), "Unexpected output: {}", output);
+    }
+
+    Ok(())
+} 
+#[test]
+fn test_bare_runtime_eval_isolated() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that each isolated script gets globals of its own
+    let result = instance.bare.eval_isolated("globalThis.tenant = 'a'; typeof Bare + ':' + tenant")?;
+    assert_eq!(result.as_string()?, "undefined:a");
+    let result = instance.bare.eval_isolated("typeof tenant")?;
+    assert_eq!(result.as_string()?, "undefined");
+    assert!(instance.bare.get_global("tenant")?.is_undefined()?);
+
+    // Test that the instance's globals are hidden from isolated scripts
+    instance.bare.eval("globalThis.secret = 42")?;
+    let result = instance.bare.eval_isolated("typeof secret")?;
+    assert_eq!(result.as_string()?, "undefined");
+
+    // Test that errors thrown in the context are reported
+    match instance.bare.eval_isolated("throw new TypeError('isolated')") {
+        Err(BareError::JSError { error_type, message, .. }) => {
+            assert_eq!(error_type, "TypeError");
+            assert_eq!(message, "isolated");
+        }
+        other => panic!("Expected JSError but got: {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_drain_with_budget() -> BareResult<()> {
+    // Test that ready work runs and the loop quiesces within the budget
+    let instance = TestInstance::new()?;
+    unsafe {
+        instance.load_script("setTimeout(() => { globalThis.done = true }, 0)")?;
+    }
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(instance.bare.drain_with_budget(100, Duration::from_secs(5))?);
+    assert!(instance.bare.get_global("done")?.as_bool()?);
+
+    // Test that a self-rescheduling promise is cut off at the deadline
+    let instance = TestInstance::new()?;
+    unsafe {
+        instance.load_script(r#"
+            setTimeout(() => {
+                function spin () { Promise.resolve().then(spin) }
+                spin()
+            }, 0)
+        "#)?;
+    }
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(!instance.bare.drain_with_budget(100, Duration::from_millis(100))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_version() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that the versions match the ones bare reports to scripts
+    let versions = instance.bare.eval("module.exports = { bare: Bare.version, v8: Bare.versions.v8 }")?;
+    assert_eq!(format!("v{}", Bare::version()?), versions.get_property("bare")?.as_string()?);
+    let engine = common::runtime().version()?;
+    assert!(engine.starts_with("v8 "), "Unexpected engine version: {}", engine);
+    assert!(engine.ends_with(&versions.get_property("v8")?.as_string()?), "Unexpected engine version: {}", engine);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_unhandled_rejection() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let reasons = Rc::new(RefCell::new(Vec::new()));
+    let sink = reasons.clone();
+    instance.bare.on_unhandled_rejection(move |reason| {
+        let message = reason.get_property("message").and_then(|m| m.as_string());
+        sink.borrow_mut().push(message.unwrap_or_default());
+    })?;
+
+    // Test that unhandled rejections reach the handler and the instance
+    // keeps running, while handled ones don't
+    unsafe {
+        instance.run_script(r#"
+            Promise.reject(new Error('nobody caught this'))
+            Promise.reject(new Error('caught')).catch(() => {})
+            setTimeout(() => { globalThis.stillRunning = true }, 10)
+        "#)?;
+    }
+    assert_eq!(*reasons.borrow(), vec!["nobody caught this".to_string()]);
+    assert!(instance.bare.get_global("stillRunning")?.as_bool()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_pending_work() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    // Test that a referenced timer is pending work and an unref'd one isn't
+    unsafe {
+        instance.load_script(r#"
+            setTimeout(() => { globalThis.fired = true }, 10)
+            setTimeout(() => {}, 60000).unref()
+        "#)?;
     }
+    assert!(instance.bare.has_pending_work());
+
+    // Test driving the loop until it is idle
+    while instance.bare.has_pending_work() {
+        instance.bare.run_once()?;
+    }
+    assert!(instance.bare.get_global("fired")?.as_bool()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_logical_filename() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+
+    let dir = std::env::temp_dir().join(format!("bare-rs-filename-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("helper.js"), "module.exports = 'helper'").unwrap();
+
+    // Test that a script without a file of its own resolves requires and
+    // sees __filename/__dirname from the location it is given
+    let filename = dir.join("[eval]");
+    let result = instance.bare.eval_with_filename(r#"
+        module.exports = { filename: __filename, dirname: __dirname, helper: require('./helper') }
+    "#, &filename.to_string_lossy());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let result = result?.to_json()?;
+    assert_eq!(result["filename"], filename.to_string_lossy().as_ref());
+    assert_eq!(result["dirname"], dir.to_string_lossy().as_ref());
+    assert_eq!(result["helper"], "helper");
+
+    Ok(())
+}
+
+#[test]
+fn test_bare_runtime_metrics() -> BareResult<()> {
+    let instance = TestInstance::new()?;
+    let before = instance.bare.metrics();
+
+    // Test that loads, runs and exceptions by type are counted
+    instance.bare.eval("1 + 1")?;
+    instance.bare.eval("null.property").unwrap_err();
+    instance.bare.load("let x = ;", "broken.js").unwrap_err();
+
+    let metrics = instance.bare.metrics();
+    assert_eq!(metrics.scripts_loaded - before.scripts_loaded, 3);
+    assert_eq!(metrics.scripts_run - before.scripts_run, 1);
+    assert_eq!(metrics.exceptions.get("TypeError"), Some(&1));
+    assert_eq!(metrics.exceptions.get("SyntaxError"), Some(&1));
+    assert!(metrics.run_time > before.run_time);
+
+    // Test that exceptions from calls into JS are counted too
+    instance.bare.eval("globalThis.fail = () => { throw new RangeError('out of range') }")?;
+    instance.bare.get_global("fail")?.call(&[]).unwrap_err();
+    assert_eq!(instance.bare.metrics().exceptions.get("RangeError"), Some(&1));
+
+    // Test the Prometheus rendering
+    let text = metrics.to_prometheus();
+    assert!(text.contains("# TYPE bare_rs_scripts_loaded_total counter"));
+    assert!(text.contains(&format!("bare_rs_scripts_loaded_total {}", metrics.scripts_loaded)));
+    assert!(text.contains("bare_rs_exceptions_total{kind=\"TypeError\"} 1"));
+
+    Ok(())
+}
+
+#[cfg(feature = "loop-per-instance")]
+#[test]
+fn test_bare_runtime_setup_on_new_loop() -> BareResult<()> {
+    let shared = common::runtime().shared();
+
+    // Test instances running concurrently on threads of their own, without
+    // TEST_MUTEX, since they don't touch the runtime's loop
+    let results = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|i| scope.spawn(move || -> BareResult<f64> {
+                let bare = shared.setup_on_new_loop(&["test"], BareOptions::default())?;
+                bare.eval(&format!("setTimeout(() => {{ globalThis.result = {} * 2 }}, 10)", i))?;
+                bare.run()?;
+                let result = bare.get_global("result")?.as_f64()?;
+                bare.teardown()?;
+                Ok(result)
+            }))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect::<BareResult<Vec<_>>>()
+    })?;
+    assert_eq!(results, vec![0.0, 2.0, 4.0, 6.0]);
 
     Ok(())
-} 
\ No newline at end of file
+}